@@ -1,6 +1,6 @@
-use crate::IntegerType;
+use crate::{token::Span, IntegerType};
 
-#[derive(Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Program {
     defs: Vec<CommandDefinition>,
 }
@@ -14,54 +14,231 @@ impl Program {
     }
 }
 
+// 定義の左辺の形から決まる命令の書き方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    Nullary, // CCB
+    Prefix,  // "D"n
+    Infix,   // n"D"m
+    Postfix, // n"D"
+}
+
+// 命令の引数。lazy な引数は評価せずに式のまま渡し、参照するたびに評価し直す
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    name: String,
+    lazy: bool,
+}
+impl Parameter {
+    pub fn new(name: String) -> Self {
+        Self { name, lazy: false }
+    }
+
+    pub fn lazy(name: String) -> Self {
+        Self { name, lazy: true }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_lazy(&self) -> bool {
+        self.lazy
+    }
+}
+
+impl std::fmt::Display for Parameter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.lazy {
+            write!(f, "~{}", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CommandDefinition {
     name: String,
-    parameters: Vec<String>,
+    kind: CommandKind,
+    parameters: Vec<Parameter>,
     expr: Box<Node>,
 }
 impl CommandDefinition {
-    pub fn new(name: String, parameters: Vec<String>, expr: Box<Node>) -> Self {
+    pub fn new(
+        name: String,
+        kind: CommandKind,
+        parameters: Vec<Parameter>,
+        expr: Box<Node>,
+    ) -> Self {
         Self {
             name,
+            kind,
             parameters,
             expr,
         }
     }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> CommandKind {
+        self.kind
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    pub fn expr(&self) -> &Node {
+        &self.expr
+    }
 }
 
+// REPL やスクリプトで一行ずつ実行する文
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Let { name: String, expr: Box<Node> },
+    Def(CommandDefinition),
+    Expr(Box<Node>),
+}
+impl Statement {
+    pub fn expr(&self) -> &Node {
+        match self {
+            Statement::Let { expr, .. } | Statement::Expr(expr) => expr,
+            Statement::Def(def) => def.expr(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Node {
     BinaryExpr {
         kind: BinaryExprKind,
         lhs: Box<Node>,
         rhs: Box<Node>,
+        span: Span,
     },
     ComparisonExpr {
         kind: ComparisonExprKind,
         lhs: Box<Node>,
         rhs: Box<Node>,
+        span: Span,
     },
     Integer(IntegerType),
-    NullaryCommand(String),
-    PrefixCommand {
-        name: String,
+    // 単項の "-"。数のリテラルに付いたものは負の数のリテラルになる
+    Negation {
+        operand: Box<Node>,
+        span: Span,
+    },
+    NullaryCall {
+        ident: String,
+        span: Span,
+    },
+    PrefixCall {
+        ident: String,
         rhs: Box<Node>,
+        span: Span,
     },
-    InfixCommand {
-        name: String,
+    InfixCall {
+        ident: String,
         lhs: Box<Node>,
         rhs: Box<Node>,
+        span: Span,
     },
-    PostfixCommand {
-        name: String,
+    PostfixCall {
+        ident: String,
+        lhs: Box<Node>,
+        span: Span,
+    },
+    DiceModifier {
+        kind: DiceModifierKind,
         lhs: Box<Node>,
+        rhs: Option<Box<Node>>,
+        span: Span,
     },
     FunctionCall {
-        name: String,
-        parameters: Vec<Box<Node>>,
+        ident: String,
+        parameters: Vec<Node>,
+        span: Span,
     },
 }
 
+// 位置は比較しない
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Node::BinaryExpr { kind, lhs, rhs, .. },
+                Node::BinaryExpr {
+                    kind: k,
+                    lhs: l,
+                    rhs: r,
+                    ..
+                },
+            ) => kind == k && lhs == l && rhs == r,
+            (
+                Node::ComparisonExpr { kind, lhs, rhs, .. },
+                Node::ComparisonExpr {
+                    kind: k,
+                    lhs: l,
+                    rhs: r,
+                    ..
+                },
+            ) => kind == k && lhs == l && rhs == r,
+            (Node::Integer(v), Node::Integer(w)) => v == w,
+            (Node::Negation { operand, .. }, Node::Negation { operand: o, .. }) => operand == o,
+            (Node::NullaryCall { ident, .. }, Node::NullaryCall { ident: i, .. }) => ident == i,
+            (
+                Node::PrefixCall { ident, rhs, .. },
+                Node::PrefixCall {
+                    ident: i, rhs: r, ..
+                },
+            ) => ident == i && rhs == r,
+            (
+                Node::InfixCall {
+                    ident, lhs, rhs, ..
+                },
+                Node::InfixCall {
+                    ident: i,
+                    lhs: l,
+                    rhs: r,
+                    ..
+                },
+            ) => ident == i && lhs == l && rhs == r,
+            (
+                Node::PostfixCall { ident, lhs, .. },
+                Node::PostfixCall {
+                    ident: i, lhs: l, ..
+                },
+            ) => ident == i && lhs == l,
+            (
+                Node::DiceModifier { kind, lhs, rhs, .. },
+                Node::DiceModifier {
+                    kind: k,
+                    lhs: l,
+                    rhs: r,
+                    ..
+                },
+            ) => kind == k && lhs == l && rhs == r,
+            (
+                Node::FunctionCall {
+                    ident, parameters, ..
+                },
+                Node::FunctionCall {
+                    ident: i,
+                    parameters: p,
+                    ..
+                },
+            ) => ident == i && parameters == p,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Node {}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BinaryExprKind {
     Add,
@@ -79,3 +256,191 @@ pub enum ComparisonExprKind {
     Gt,
     Ge,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiceModifierKind {
+    KeepHighest,
+    KeepLowest,
+    DropHighest,
+    DropLowest,
+    Explode(ComparisonExprKind),
+    Compound(ComparisonExprKind),
+    Penetrate(ComparisonExprKind),
+    Reroll(ComparisonExprKind),
+    RerollOnce(ComparisonExprKind),
+}
+
+pub const COMPARISON_PRECEDENCE: u8 = 0;
+pub const UNARY_PRECEDENCE: u8 = 3;
+pub const PRIMARY_PRECEDENCE: u8 = 4;
+
+impl Node {
+    // 整数のリテラルは評価に失敗しないため位置を持たない
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Node::Integer(_) => None,
+            Node::BinaryExpr { span, .. }
+            | Node::ComparisonExpr { span, .. }
+            | Node::Negation { span, .. }
+            | Node::NullaryCall { span, .. }
+            | Node::PrefixCall { span, .. }
+            | Node::InfixCall { span, .. }
+            | Node::PostfixCall { span, .. }
+            | Node::DiceModifier { span, .. }
+            | Node::FunctionCall { span, .. } => Some(*span),
+        }
+    }
+
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Node::ComparisonExpr { .. } => COMPARISON_PRECEDENCE,
+            Node::BinaryExpr { kind, .. } => kind.precedence(),
+            Node::Negation { .. } => UNARY_PRECEDENCE,
+            _ => PRIMARY_PRECEDENCE,
+        }
+    }
+}
+
+impl BinaryExprKind {
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryExprKind::Add | BinaryExprKind::Sub => 1,
+            BinaryExprKind::Mul | BinaryExprKind::Div => 2,
+        }
+    }
+}
+
+// 二項演算の被演算子を括弧で囲まないと式の構造が変わってしまうか
+pub fn needs_parens(parent: u8, operand: u8, is_rhs: bool) -> bool {
+    operand < parent || (operand == parent && (is_rhs || parent == COMPARISON_PRECEDENCE))
+}
+
+impl std::fmt::Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operand = |child: &Node, is_rhs: bool| {
+            // 負の数を命令の引数にすると "-2D6" のように単項の "-" と区別できない
+            let parens = match self {
+                Node::BinaryExpr { .. } | Node::ComparisonExpr { .. } | Node::Negation { .. } => {
+                    needs_parens(self.precedence(), child.precedence(), is_rhs)
+                }
+                _ => !matches!(child, Node::Integer(v) if *v >= 0),
+            };
+
+            if parens {
+                format!("({})", child)
+            } else {
+                child.to_string()
+            }
+        };
+
+        match self {
+            Node::BinaryExpr { kind, lhs, rhs, .. } => {
+                write!(f, "{}{}{}", operand(lhs, false), kind, operand(rhs, true))
+            }
+            Node::ComparisonExpr { kind, lhs, rhs, .. } => {
+                write!(f, "{}{}{}", operand(lhs, false), kind, operand(rhs, true))
+            }
+            Node::Integer(v) => write!(f, "{}", v),
+            Node::Negation { operand: o, .. } => write!(f, "-{}", operand(o, true)),
+            Node::NullaryCall { ident, .. } => write!(f, "{}", ident),
+            Node::PrefixCall { ident, rhs, .. } => write!(f, "{}{}", ident, operand(rhs, true)),
+            Node::InfixCall {
+                ident, lhs, rhs, ..
+            } => {
+                write!(f, "{}{}{}", operand(lhs, false), ident, operand(rhs, true))
+            }
+            Node::PostfixCall { ident, lhs, .. } => write!(f, "{}{}", operand(lhs, false), ident),
+            Node::DiceModifier { kind, lhs, rhs, .. } => {
+                write!(f, "{}{}", lhs, kind)?;
+
+                if let Some(rhs) = rhs {
+                    match kind.comparison() {
+                        Some(ComparisonExprKind::Eq) | None => {}
+                        Some(comparison) => write!(f, "{}", comparison)?,
+                    }
+
+                    write!(f, "{}", operand(rhs, true))?;
+                }
+
+                Ok(())
+            }
+            Node::FunctionCall {
+                ident, parameters, ..
+            } => write!(
+                f,
+                "{}({})",
+                ident,
+                parameters
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for BinaryExprKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                BinaryExprKind::Add => "+",
+                BinaryExprKind::Sub => "-",
+                BinaryExprKind::Mul => "*",
+                BinaryExprKind::Div => "/",
+            }
+        )
+    }
+}
+
+impl std::fmt::Display for ComparisonExprKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                ComparisonExprKind::Eq => "=",
+                ComparisonExprKind::Ne => "<>",
+                ComparisonExprKind::Lt => "<",
+                ComparisonExprKind::Le => "<=",
+                ComparisonExprKind::Gt => ">",
+                ComparisonExprKind::Ge => ">=",
+            }
+        )
+    }
+}
+
+impl DiceModifierKind {
+    pub fn comparison(&self) -> Option<ComparisonExprKind> {
+        match self {
+            DiceModifierKind::Explode(c)
+            | DiceModifierKind::Compound(c)
+            | DiceModifierKind::Penetrate(c)
+            | DiceModifierKind::Reroll(c)
+            | DiceModifierKind::RerollOnce(c) => Some(*c),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for DiceModifierKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                DiceModifierKind::KeepHighest => "KH",
+                DiceModifierKind::KeepLowest => "KL",
+                DiceModifierKind::DropHighest => "DH",
+                DiceModifierKind::DropLowest => "DL",
+                DiceModifierKind::Explode(_) => "!",
+                DiceModifierKind::Compound(_) => "!!",
+                DiceModifierKind::Penetrate(_) => "!p",
+                DiceModifierKind::Reroll(_) => "r",
+                DiceModifierKind::RerollOnce(_) => "ro",
+            }
+        )
+    }
+}
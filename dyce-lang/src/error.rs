@@ -0,0 +1,72 @@
+use crate::{token::Span, IntegerType};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    span: Span,
+}
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
+    pub fn kind(&self) -> &ParseErrorKind {
+        &self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    // 字句解析
+    IllegalCharacter(String),
+    // 構文解析
+    UnexpectedToken { expected: String, found: String },
+    UnexpectedEof { expected: String },
+    UnexpectedEol { expected: String },
+    InvalidNumber(String),
+    NumberOutOfRange(String),
+    NestingTooDeep(usize),
+}
+
+impl std::fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErrorKind::IllegalCharacter(c) => write!(f, "illegal character '{}'", c),
+            ParseErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected {}, found '{}'", expected, found)
+            }
+            ParseErrorKind::UnexpectedEof { expected } => {
+                write!(f, "expected {}, found end of input", expected)
+            }
+            ParseErrorKind::UnexpectedEol { expected } => {
+                write!(f, "expected {}, found end of line", expected)
+            }
+            ParseErrorKind::InvalidNumber(literal) => write!(f, "invalid number '{}'", literal),
+            ParseErrorKind::NumberOutOfRange(literal) => write!(
+                f,
+                "number '{}' is out of range (at most {})",
+                literal,
+                IntegerType::MAX
+            ),
+            ParseErrorKind::NestingTooDeep(max) => {
+                write!(
+                    f,
+                    "expression is nested too deeply (at most {} levels)",
+                    max
+                )
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::token::{Token, TokenKind};
+use crate::token::{Span, Token, TokenKind};
 
 pub struct Lexer {
     input: String,
@@ -50,7 +50,7 @@ impl Lexer {
         let mut num = String::new();
 
         while let Some(ch) = self.ch {
-            if !ch.is_digit(10) {
+            if !ch.is_ascii_digit() {
                 break;
             }
 
@@ -79,6 +79,13 @@ impl Lexer {
     pub fn next_token(&mut self) -> Option<Token> {
         self.skip_whitespace();
 
+        let start = self.position;
+        let token = self.read_token()?;
+
+        Some(token.with_span(Span::new(start, self.position)))
+    }
+
+    fn read_token(&mut self) -> Option<Token> {
         let ch = self.ch?;
         let mut literal = ch.to_string();
         let kind = match ch {
@@ -108,7 +115,7 @@ impl Lexer {
 
                     TokenKind::Ne
                 } else {
-                    TokenKind::Illegal
+                    TokenKind::Bang
                 }
             }
             '<' => {
@@ -137,6 +144,7 @@ impl Lexer {
                 }
             }
             ',' => TokenKind::Comma,
+            '~' => TokenKind::Tilde,
             '"' => TokenKind::DoubleQuote,
             '(' => TokenKind::LParen,
             ')' => TokenKind::RParen,
@@ -146,7 +154,7 @@ impl Lexer {
                 return Some(Token::new(TokenKind::Separator, literal));
             }
             _ => {
-                if ch.is_digit(10) {
+                if ch.is_ascii_digit() {
                     return Some(Token::new(TokenKind::Number, self.read_number()));
                 }
                 if ch.is_ascii_alphabetic() {
@@ -170,6 +178,30 @@ mod test {
     #[test]
     fn def_tokenize_test() {
         let tests = [
+            (
+                "let STR = 3D6",
+                vec![
+                    Token::new(TokenKind::Ident, "let".to_string()),
+                    Token::new(TokenKind::Ident, "STR".to_string()),
+                    Token::new(TokenKind::Eq, "=".to_string()),
+                    Token::new(TokenKind::Number, "3".to_string()),
+                    Token::new(TokenKind::Ident, "D".to_string()),
+                    Token::new(TokenKind::Number, "6".to_string()),
+                ],
+            ),
+            (
+                r#"def n"B"m => (n)D(m)"#,
+                vec![
+                    Token::new(TokenKind::Ident, "def".to_string()),
+                    Token::new(TokenKind::Ident, "n".to_string()),
+                    Token::new(TokenKind::DoubleQuote, '"'.to_string()),
+                    Token::new(TokenKind::Ident, "B".to_string()),
+                    Token::new(TokenKind::DoubleQuote, '"'.to_string()),
+                    Token::new(TokenKind::Ident, "m".to_string()),
+                    Token::new(TokenKind::Arrow, "=>".to_string()),
+                    Token::new(TokenKind::LParen, "(".to_string()),
+                ],
+            ),
             (
                 "CCB => 1D100",
                 vec![
@@ -197,13 +229,33 @@ mod test {
                     Token::new(TokenKind::RParen, ")".to_string()),
                 ],
             ),
+            (
+                r#"def "twice"~x => x+x"#,
+                vec![
+                    Token::new(TokenKind::Ident, "def".to_string()),
+                    Token::new(TokenKind::DoubleQuote, '"'.to_string()),
+                    Token::new(TokenKind::Ident, "twice".to_string()),
+                    Token::new(TokenKind::DoubleQuote, '"'.to_string()),
+                    Token::new(TokenKind::Tilde, "~".to_string()),
+                    Token::new(TokenKind::Ident, "x".to_string()),
+                    Token::new(TokenKind::Arrow, "=>".to_string()),
+                ],
+            ),
         ];
+
+        for (input, expected) in tests {
+            let mut lexer = Lexer::new(input.to_string());
+
+            for token in expected {
+                assert_eq!(lexer.next_token().unwrap(), token);
+            }
+        }
     }
 
     #[test]
     fn separator_tokenize_test() {
         let tests = [(
-            "1\n2",
+            "1\n\n2",
             vec![
                 Token::new(TokenKind::Number, "1".to_string()),
                 Token::new(TokenKind::Separator, "\n".to_string()),
@@ -220,6 +272,40 @@ mod test {
         }
     }
 
+    #[test]
+    fn span_tokenize_test() {
+        let tests = [
+            (
+                "1D6 + *2",
+                vec![(0, 1), (1, 2), (2, 3), (4, 5), (6, 7), (7, 8)],
+            ),
+            (
+                "x\"D\"y =>\n  xDy",
+                vec![
+                    (0, 1),
+                    (1, 2),
+                    (2, 3),
+                    (3, 4),
+                    (4, 5),
+                    (6, 8),
+                    (8, 9),
+                    (11, 14),
+                ],
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut lexer = Lexer::new(input.to_string());
+            let mut spans = Vec::new();
+
+            while let Some(token) = lexer.next_token() {
+                spans.push((token.span().start(), token.span().end()));
+            }
+
+            assert_eq!(spans, expected);
+        }
+    }
+
     #[test]
     fn ident_tokenize_test() {
         let inputs = ["D", "CCB", "dx"];
@@ -379,6 +465,28 @@ mod test {
                     Token::new(TokenKind::Number, "50".to_string()),
                 ],
             ),
+            (
+                "3D6!!",
+                vec![
+                    Token::new(TokenKind::Number, "3".to_string()),
+                    Token::new(TokenKind::Ident, "D".to_string()),
+                    Token::new(TokenKind::Number, "6".to_string()),
+                    Token::new(TokenKind::Bang, "!".to_string()),
+                    Token::new(TokenKind::Bang, "!".to_string()),
+                ],
+            ),
+            (
+                "1D10!p>=9",
+                vec![
+                    Token::new(TokenKind::Number, "1".to_string()),
+                    Token::new(TokenKind::Ident, "D".to_string()),
+                    Token::new(TokenKind::Number, "10".to_string()),
+                    Token::new(TokenKind::Bang, "!".to_string()),
+                    Token::new(TokenKind::Ident, "p".to_string()),
+                    Token::new(TokenKind::Ge, ">=".to_string()),
+                    Token::new(TokenKind::Number, "9".to_string()),
+                ],
+            ),
         ];
 
         for (input, expected) in tests {
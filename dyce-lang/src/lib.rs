@@ -1,6 +1,7 @@
-type IntegerType = i128;
+pub type IntegerType = i128;
 
-mod ast;
-mod lexer;
-mod parser;
-mod token;
+pub mod ast;
+pub mod error;
+pub mod lexer;
+pub mod parser;
+pub mod token;
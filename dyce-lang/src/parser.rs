@@ -1,19 +1,34 @@
 use crate::{
-    ast::{BinaryExprKind, CommandDefinition, ComparisonExprKind, Node, Program},
+    ast::{
+        BinaryExprKind, CommandDefinition, CommandKind, ComparisonExprKind, DiceModifierKind, Node,
+        Parameter, Program, Statement,
+    },
+    error::{ParseError, ParseErrorKind},
     lexer::Lexer,
-    token::{Token, TokenKind},
+    token::{Span, Token, TokenKind},
     IntegerType,
 };
+use std::num::IntErrorKind;
+
+// 括弧や単項演算子、関数呼び出しの入れ子の上限。深い入れ子でスタックを使い切らないようにする
+pub const MAX_NESTING: usize = 100;
 
 pub struct Parser {
     lexer: Lexer,
     cur_token: Option<Token>,
+    last_span: Span,
+    // 定義を並べるときだけ改行を区切りとして扱う。式の中では空白と同じ
+    separators: bool,
+    nesting: usize,
 }
 impl Parser {
     pub fn new(lexer: Lexer) -> Self {
         let mut parser = Self {
             lexer,
             cur_token: None,
+            last_span: Span::default(),
+            separators: false,
+            nesting: 0,
         };
 
         parser.read_token();
@@ -22,10 +37,31 @@ impl Parser {
     }
 
     fn read_token(&mut self) {
+        if let Some(t) = &self.cur_token {
+            self.last_span = t.span();
+        }
+
         self.cur_token = self.lexer.next_token();
+
+        while !self.separators && self.peek(TokenKind::Separator) {
+            self.cur_token = self.lexer.next_token();
+        }
     }
 
-    fn peek(&mut self, expected: TokenKind) -> bool {
+    // 現在のトークンの位置。入力の終わりでは最後のトークンの直後を指す
+    fn cur_span(&self) -> Span {
+        match &self.cur_token {
+            Some(t) => t.span(),
+            None => Span::new(self.last_span.end(), self.last_span.end() + 1),
+        }
+    }
+
+    // start から最後に読んだトークンまでの範囲
+    fn span_from(&self, start: Span) -> Span {
+        start.to(self.last_span)
+    }
+
+    fn peek(&self, expected: TokenKind) -> bool {
         if let Some(t) = &self.cur_token {
             t.kind() == expected
         } else {
@@ -47,68 +83,137 @@ impl Parser {
         Some(token)
     }
 
-    fn expect(&mut self, expected: TokenKind) -> Result<Token, ()> {
-        let token = self.cur_token.as_ref().ok_or(())?;
+    fn expect(&mut self, expected: TokenKind) -> Result<Token, ParseError> {
+        self.consume(expected)
+            .ok_or_else(|| self.unexpected(&expected.to_string()))
+    }
 
-        if token.kind() != expected {
-            return Err(());
-        }
+    fn unexpected(&self, expected: &str) -> ParseError {
+        let kind = match &self.cur_token {
+            Some(t) if t.kind() == TokenKind::Illegal => {
+                ParseErrorKind::IllegalCharacter(t.literal())
+            }
+            // 区切りの改行をそのまま表示すると行が割れてしまう
+            Some(t) if t.kind() == TokenKind::Separator => ParseErrorKind::UnexpectedEol {
+                expected: expected.to_string(),
+            },
+            Some(t) => ParseErrorKind::UnexpectedToken {
+                expected: expected.to_string(),
+                found: t.literal(),
+            },
+            None => ParseErrorKind::UnexpectedEof {
+                expected: expected.to_string(),
+            },
+        };
 
-        let token = token.clone();
+        ParseError::new(kind, self.cur_span())
+    }
 
-        self.read_token();
+    fn is_eof(&self) -> bool {
+        self.cur_token.is_none()
+    }
+
+    pub fn parse(&mut self) -> Result<Box<Node>, ParseError> {
+        let node = self.expr()?;
 
-        Ok(token)
+        if self.cur_token.is_some() {
+            return Err(self.unexpected("end of input"));
+        }
+
+        Ok(node)
     }
 
-    fn is_eof(&self) -> bool {
-        self.cur_token == None
+    pub fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        let statement = self.statement()?;
+
+        if self.cur_token.is_some() {
+            return Err(self.unexpected("end of input"));
+        }
+
+        Ok(statement)
     }
 
-    pub fn parse(&mut self) -> Result<Program, ()> {
+    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
+        self.separators = true;
+
         self.program()
     }
 
-    // program = ( def "\n"+ )*
-    fn program(&mut self) -> Result<Program, ()> {
+    // program = "\n"* ( definition ( "\n"+ | EOF ) )*
+    fn program(&mut self) -> Result<Program, ParseError> {
         let mut program = Program::default();
 
-        while self.is_eof() {
-            program.push(self.def()?);
-            self.expect(TokenKind::Separator)?;
+        self.consume(TokenKind::Separator);
+
+        while !self.is_eof() {
+            program.push(self.definition()?);
+
+            if !self.is_eof() {
+                self.expect(TokenKind::Separator)?;
+            }
         }
 
         Ok(program)
     }
 
-    // def = pat "=>" expr
-    fn def(&mut self) -> Result<CommandDefinition, ()> {
-        let (name, parameters) = self.pat()?;
+    // statement = "let" string "=" expr | "def" definition | expr
+    fn statement(&mut self) -> Result<Statement, ParseError> {
+        if self.peek_keyword("let") {
+            self.read_token();
+
+            let name = self.expect(TokenKind::Ident)?.literal();
+
+            self.expect(TokenKind::Eq)?;
+
+            return Ok(Statement::Let {
+                name,
+                expr: self.expr()?,
+            });
+        }
+
+        if self.peek_keyword("def") {
+            self.read_token();
+
+            return Ok(Statement::Def(self.definition()?));
+        }
+
+        Ok(Statement::Expr(self.expr()?))
+    }
+
+    // definition = pat "=>" expr
+    fn definition(&mut self) -> Result<CommandDefinition, ParseError> {
+        let (name, kind, parameters) = self.pat()?;
 
         self.expect(TokenKind::Arrow)?;
 
         let expr = self.expr()?;
 
-        Ok(CommandDefinition::new(name, parameters, expr))
+        Ok(CommandDefinition::new(name, kind, parameters, expr))
     }
 
-    // pat = string | string '"' string '"' | string '"' string '"' string | '"' string '"' string
-    fn pat(&mut self) -> Result<(String, Vec<String>), ()> {
-        if let Some(token) = self.consume(TokenKind::Ident) {
-            if self.consume(TokenKind::DoubleQuote).is_some() {
-                let mut parameters = vec![token.literal()];
-                let name = self.expect(TokenKind::Ident)?.literal();
+    // pat = string | param '"' string '"' | param '"' string '"' param | '"' string '"' param
+    fn pat(&mut self) -> Result<(String, CommandKind, Vec<Parameter>), ParseError> {
+        if !self.peek(TokenKind::DoubleQuote) {
+            let lhs = self.parameter()?;
 
-                self.expect(TokenKind::DoubleQuote)?;
+            // 無引数の命令の名前には "~" を付けられない
+            if !lhs.is_lazy() && !self.peek(TokenKind::DoubleQuote) {
+                return Ok((lhs.name().to_string(), CommandKind::Nullary, Vec::new()));
+            }
 
-                if let Some(token) = self.consume(TokenKind::Ident) {
-                    parameters.push(token.literal());
-                }
+            self.expect(TokenKind::DoubleQuote)?;
+
+            let name = self.expect(TokenKind::Ident)?.literal();
 
-                return Ok((name, parameters));
+            self.expect(TokenKind::DoubleQuote)?;
+
+            if self.peek(TokenKind::Ident) || self.peek(TokenKind::Tilde) {
+                let rhs = self.parameter()?;
+
+                return Ok((name, CommandKind::Infix, vec![lhs, rhs]));
             }
 
-            return Ok((token.literal(), Vec::new()));
+            return Ok((name, CommandKind::Postfix, vec![lhs]));
         }
 
         self.expect(TokenKind::DoubleQuote)?;
@@ -117,18 +222,29 @@ impl Parser {
 
         self.expect(TokenKind::DoubleQuote)?;
 
-        let param = self.expect(TokenKind::Ident)?.literal();
+        Ok((name, CommandKind::Prefix, vec![self.parameter()?]))
+    }
 
-        Ok((name, vec![param]))
+    // param = "~"? string
+    fn parameter(&mut self) -> Result<Parameter, ParseError> {
+        let lazy = self.consume(TokenKind::Tilde).is_some();
+        let name = self.expect(TokenKind::Ident)?.literal();
+
+        Ok(if lazy {
+            Parameter::lazy(name)
+        } else {
+            Parameter::new(name)
+        })
     }
 
     // expr = equality
-    fn expr(&mut self) -> Result<Box<Node>, ()> {
+    fn expr(&mut self) -> Result<Box<Node>, ParseError> {
         self.equality()
     }
 
     // equality = relational ( "=" relational | "==" relational | "!=" relational | "<>" relational )
-    fn equality(&mut self) -> Result<Box<Node>, ()> {
+    fn equality(&mut self) -> Result<Box<Node>, ParseError> {
+        let start = self.cur_span();
         let mut node = self.relational()?;
 
         if self.consume(TokenKind::Eq).is_some() {
@@ -136,12 +252,14 @@ impl Parser {
                 kind: ComparisonExprKind::Eq,
                 lhs: node,
                 rhs: self.relational()?,
+                span: self.span_from(start),
             })
         } else if self.consume(TokenKind::Ne).is_some() {
             node = Box::new(Node::ComparisonExpr {
                 kind: ComparisonExprKind::Ne,
                 lhs: node,
                 rhs: self.relational()?,
+                span: self.span_from(start),
             })
         }
 
@@ -149,7 +267,8 @@ impl Parser {
     }
 
     // relational = add ( "<" add | "<=" add | ">" add | ">" add )
-    fn relational(&mut self) -> Result<Box<Node>, ()> {
+    fn relational(&mut self) -> Result<Box<Node>, ParseError> {
+        let start = self.cur_span();
         let mut node = self.add()?;
 
         if self.consume(TokenKind::Lt).is_some() {
@@ -157,24 +276,28 @@ impl Parser {
                 kind: ComparisonExprKind::Lt,
                 lhs: node,
                 rhs: self.add()?,
+                span: self.span_from(start),
             })
         } else if self.consume(TokenKind::Le).is_some() {
             node = Box::new(Node::ComparisonExpr {
                 kind: ComparisonExprKind::Le,
                 lhs: node,
                 rhs: self.add()?,
+                span: self.span_from(start),
             })
         } else if self.consume(TokenKind::Gt).is_some() {
             node = Box::new(Node::ComparisonExpr {
                 kind: ComparisonExprKind::Gt,
                 lhs: node,
                 rhs: self.add()?,
+                span: self.span_from(start),
             })
         } else if self.consume(TokenKind::Ge).is_some() {
             node = Box::new(Node::ComparisonExpr {
                 kind: ComparisonExprKind::Ge,
                 lhs: node,
                 rhs: self.add()?,
+                span: self.span_from(start),
             })
         }
 
@@ -182,7 +305,8 @@ impl Parser {
     }
 
     // add = mul ( "+" mul | "-" mul )*
-    fn add(&mut self) -> Result<Box<Node>, ()> {
+    fn add(&mut self) -> Result<Box<Node>, ParseError> {
+        let start = self.cur_span();
         let mut node = self.mul()?;
 
         loop {
@@ -191,12 +315,14 @@ impl Parser {
                     kind: BinaryExprKind::Add,
                     lhs: node,
                     rhs: self.mul()?,
+                    span: self.span_from(start),
                 })
             } else if self.consume(TokenKind::Minus).is_some() {
                 node = Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Sub,
                     lhs: node,
                     rhs: self.mul()?,
+                    span: self.span_from(start),
                 })
             } else {
                 return Ok(node);
@@ -204,22 +330,25 @@ impl Parser {
         }
     }
 
-    // mul = call ( "*" call | "/" call )*
-    fn mul(&mut self) -> Result<Box<Node>, ()> {
-        let mut node = self.call()?;
+    // mul = unary ( "*" unary | "/" unary )*
+    fn mul(&mut self) -> Result<Box<Node>, ParseError> {
+        let start = self.cur_span();
+        let mut node = self.unary()?;
 
         loop {
             if self.consume(TokenKind::Asterisk).is_some() {
                 node = Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Mul,
                     lhs: node,
-                    rhs: self.call()?,
+                    rhs: self.unary()?,
+                    span: self.span_from(start),
                 })
             } else if self.consume(TokenKind::Slash).is_some() {
                 node = Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Div,
                     lhs: node,
-                    rhs: self.call()?,
+                    rhs: self.unary()?,
+                    span: self.span_from(start),
                 })
             } else {
                 return Ok(node);
@@ -227,37 +356,83 @@ impl Parser {
         }
     }
 
-    // call = primary | string "(" expr*, ")" | string | string primary | primary string primary | primary string
-    fn call(&mut self) -> Result<Box<Node>, ()> {
-        // string | string primary
+    // 入れ子になった式は全て unary を通るので、ここで深さを数える
+    fn unary(&mut self) -> Result<Box<Node>, ParseError> {
+        if self.nesting >= MAX_NESTING {
+            return Err(ParseError::new(
+                ParseErrorKind::NestingTooDeep(MAX_NESTING),
+                self.cur_span(),
+            ));
+        }
+
+        self.nesting += 1;
+
+        let node = self.unary_operand();
+
+        self.nesting -= 1;
+
+        node
+    }
+
+    // unary = "-" unary | "+" unary | call
+    fn unary_operand(&mut self) -> Result<Box<Node>, ParseError> {
+        let start = self.cur_span();
+
+        // 単項の "-" はダイスより弱く結合する。-2D6 は -(2D6)
+        if self.consume(TokenKind::Minus).is_some() {
+            let operand = self.unary()?;
+
+            return Ok(match *operand {
+                Node::Integer(v) => Box::new(Node::Integer(-v)),
+                operand => Box::new(Node::Negation {
+                    operand: Box::new(operand),
+                    span: self.span_from(start),
+                }),
+            });
+        }
+
+        if self.consume(TokenKind::Plus).is_some() {
+            return self.unary();
+        }
+
+        self.call()
+    }
+
+    // call = primary | string "(" ( expr ( "," expr )* )? ")" | string | string primary | primary string primary modifier* | primary string
+    fn call(&mut self) -> Result<Box<Node>, ParseError> {
+        let start = self.cur_span();
+
+        // string "(" ( expr ( "," expr )* )? ")" | string | string primary
         if let Some(token) = self.consume(TokenKind::Ident) {
-            let name = token.literal();
+            let ident = token.literal();
 
-            // string "(" expr*, ")" | string ( expr )
+            // 引数が一つのときは string primary と同じ前置の呼び出しになる
             if self.consume(TokenKind::LParen).is_some() {
-                // string()
-                if self.consume(TokenKind::RParen).is_some() {
-                    return Ok(Box::new(Node::FunctionCall {
-                        name,
-                        parameters: Vec::new(),
-                    }));
-                }
+                let mut parameters = Vec::new();
 
-                let mut parameters = vec![self.expr()?];
+                if !self.peek(TokenKind::RParen) {
+                    parameters.push(*self.expr()?);
 
-                while self.consume(TokenKind::Comma).is_some() {
-                    parameters.push(self.expr()?);
+                    while self.consume(TokenKind::Comma).is_some() {
+                        parameters.push(*self.expr()?);
+                    }
                 }
 
+                self.expect(TokenKind::RParen)?;
+
+                let span = self.span_from(start);
                 let node = if parameters.len() == 1 {
-                    // string primary
-                    Node::PrefixCommand {
-                        name,
-                        rhs: parameters[0].clone(),
+                    Node::PrefixCall {
+                        ident,
+                        rhs: Box::new(parameters.remove(0)),
+                        span,
                     }
                 } else {
-                    // string "(" expr*, ")"
-                    Node::FunctionCall { name, parameters }
+                    Node::FunctionCall {
+                        ident,
+                        parameters,
+                        span,
+                    }
                 };
 
                 return Ok(Box::new(node));
@@ -267,33 +442,182 @@ impl Parser {
             if self.peek(TokenKind::Number) {
                 let rhs = self.primary()?;
 
-                return Ok(Box::new(Node::PrefixCommand { name, rhs }));
+                return Ok(Box::new(Node::PrefixCall {
+                    ident,
+                    rhs,
+                    span: self.span_from(start),
+                }));
             }
 
             // string
-            return Ok(Box::new(Node::NullaryCommand(name)));
+            return Ok(Box::new(Node::NullaryCall {
+                ident,
+                span: self.span_from(start),
+            }));
         }
 
         // primary | primary string primary | primary string
         let lhs = self.primary()?;
-        let name = match self.consume(TokenKind::Ident) {
+        let ident = match self.consume(TokenKind::Ident) {
             Some(t) => t.literal(),
             _ => return Ok(lhs), // primary
         };
 
-        // primary string primary
+        // primary string primary modifier*
         if self.peek(TokenKind::Number) || self.peek(TokenKind::LParen) {
             let rhs = self.primary()?;
 
-            return Ok(Box::new(Node::InfixCommand { name, lhs, rhs }));
+            let node = Box::new(Node::InfixCall {
+                ident,
+                lhs,
+                rhs,
+                span: self.span_from(start),
+            });
+
+            return self.modifiers(node, start);
         }
 
         // primary string
-        Ok(Box::new(Node::PostfixCommand { name, lhs }))
+        Ok(Box::new(Node::PostfixCall {
+            ident,
+            lhs,
+            span: self.span_from(start),
+        }))
+    }
+
+    // modifier = ( "kh" | "kl" | "dh" | "dl" ) primary? | "!" ( "!" | "p" )? target | ( "r" | "ro" ) target
+    fn modifiers(&mut self, lhs: Box<Node>, start: Span) -> Result<Box<Node>, ParseError> {
+        let mut node = lhs;
+
+        loop {
+            if let Some(kind) = self.peek_modifier() {
+                self.read_token();
+
+                node = Box::new(Node::DiceModifier {
+                    kind,
+                    lhs: node,
+                    rhs: self.optional_primary()?,
+                    span: self.span_from(start),
+                });
+            } else if self.consume(TokenKind::Bang).is_some() || self.split_bang() {
+                let explosion = if self.consume(TokenKind::Bang).is_some() || self.split_bang() {
+                    DiceModifierKind::Compound
+                } else if self.peek_literal("p") {
+                    self.read_token();
+
+                    DiceModifierKind::Penetrate
+                } else {
+                    DiceModifierKind::Explode
+                };
+                let (comparison, rhs) = self.target()?;
+
+                node = Box::new(Node::DiceModifier {
+                    kind: explosion(comparison),
+                    lhs: node,
+                    rhs,
+                    span: self.span_from(start),
+                });
+            } else if self.peek_literal("r") || self.peek_literal("ro") {
+                let reroll = if self.peek_literal("r") {
+                    DiceModifierKind::Reroll
+                } else {
+                    DiceModifierKind::RerollOnce
+                };
+
+                self.read_token();
+
+                let (comparison, rhs) = self.target()?;
+
+                node = Box::new(Node::DiceModifier {
+                    kind: reroll(comparison),
+                    lhs: node,
+                    rhs,
+                    span: self.span_from(start),
+                });
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    // target = ( ( "=" | "!=" | "<>" | "<" | "<=" | ">" | ">=" ) primary | primary )?
+    fn target(&mut self) -> Result<(ComparisonExprKind, Option<Box<Node>>), ParseError> {
+        let kind = if self.consume(TokenKind::Eq).is_some() {
+            ComparisonExprKind::Eq
+        } else if self.consume(TokenKind::Ne).is_some() {
+            ComparisonExprKind::Ne
+        } else if self.consume(TokenKind::Lt).is_some() {
+            ComparisonExprKind::Lt
+        } else if self.consume(TokenKind::Le).is_some() {
+            ComparisonExprKind::Le
+        } else if self.consume(TokenKind::Gt).is_some() {
+            ComparisonExprKind::Gt
+        } else if self.consume(TokenKind::Ge).is_some() {
+            ComparisonExprKind::Ge
+        } else {
+            return Ok((ComparisonExprKind::Eq, self.optional_primary()?));
+        };
+
+        Ok((kind, Some(self.primary()?)))
+    }
+
+    // 空白を挟まずに修飾子に続く "!=" は、比較ではなく "!" と条件の "=" として読む
+    fn split_bang(&mut self) -> bool {
+        let span = match &self.cur_token {
+            Some(t) if t.literal() == "!=" && t.span().start() == self.last_span.end() => t.span(),
+            _ => return false,
+        };
+
+        self.last_span = Span::new(span.start(), span.start() + 1);
+        self.cur_token = Some(
+            Token::new(TokenKind::Eq, "=".to_string())
+                .with_span(Span::new(span.start() + 1, span.end())),
+        );
+
+        true
+    }
+
+    fn optional_primary(&mut self) -> Result<Option<Box<Node>>, ParseError> {
+        if self.peek(TokenKind::Number) || self.peek(TokenKind::LParen) {
+            Ok(Some(self.primary()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn peek_literal(&self, expected: &str) -> bool {
+        match &self.cur_token {
+            Some(t) => t.kind() == TokenKind::Ident && t.literal().eq_ignore_ascii_case(expected),
+            None => false,
+        }
+    }
+
+    // キーワードは大文字小文字を区別する。"LET" は普通の命令名として使える
+    fn peek_keyword(&self, expected: &str) -> bool {
+        match &self.cur_token {
+            Some(t) => t.kind() == TokenKind::Ident && t.literal() == expected,
+            None => false,
+        }
+    }
+
+    fn peek_modifier(&self) -> Option<DiceModifierKind> {
+        let token = self.cur_token.as_ref()?;
+
+        if token.kind() != TokenKind::Ident {
+            return None;
+        }
+
+        match token.literal().to_ascii_lowercase().as_str() {
+            "kh" => Some(DiceModifierKind::KeepHighest),
+            "kl" => Some(DiceModifierKind::KeepLowest),
+            "dh" => Some(DiceModifierKind::DropHighest),
+            "dl" => Some(DiceModifierKind::DropLowest),
+            _ => None,
+        }
     }
 
     // primary = number | "(" expr ")"
-    fn primary(&mut self) -> Result<Box<Node>, ()> {
+    fn primary(&mut self) -> Result<Box<Node>, ParseError> {
         if self.consume(TokenKind::LParen).is_some() {
             let node = self.expr()?;
 
@@ -302,14 +626,24 @@ impl Parser {
             return Ok(node);
         }
 
-        if let Some(name) = self.consume(TokenKind::Ident) {}
+        let token = match self.consume(TokenKind::Number) {
+            Some(t) => t,
+            None => return Err(self.unexpected("number or '('")),
+        };
+
+        // 字句解析は数字の並びしか数として返さないので、失敗するのは桁あふれ
+        let value = token.literal().parse::<IntegerType>().map_err(|e| {
+            let kind = match e.kind() {
+                IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+                    ParseErrorKind::NumberOutOfRange(token.literal())
+                }
+                _ => ParseErrorKind::InvalidNumber(token.literal()),
+            };
 
-        Ok(Box::new(Node::Integer(
-            self.expect(TokenKind::Number)?
-                .literal()
-                .parse::<IntegerType>()
-                .or(Err(()))?,
-        )))
+            ParseError::new(kind, token.span())
+        })?;
+
+        Ok(Box::new(Node::Integer(value)))
     }
 }
 
@@ -322,32 +656,716 @@ mod test {
         let tests = [
             (
                 "1D6",
-                Node::InfixCommand {
-                    name: "D".to_string(),
+                Node::InfixCall {
+                    ident: "D".to_string(),
                     lhs: Box::new(Node::Integer(1)),
                     rhs: Box::new(Node::Integer(6)),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "CCB",
+                Node::NullaryCall {
+                    ident: "CCB".to_string(),
+                    span: Span::default(),
                 },
             ),
-            ("CCB", Node::NullaryCommand("CCB".to_string())),
             (
                 "d6",
-                Node::PrefixCommand {
-                    name: "d".to_string(),
+                Node::PrefixCall {
+                    ident: "d".to_string(),
                     rhs: Box::new(Node::Integer(6)),
+                    span: Span::default(),
                 },
             ),
             (
                 "2d",
-                Node::PostfixCommand {
-                    name: "d".to_string(),
+                Node::PostfixCall {
+                    ident: "d".to_string(),
                     lhs: Box::new(Node::Integer(2)),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "adv(1+2)",
+                Node::PrefixCall {
+                    ident: "adv".to_string(),
+                    rhs: Box::new(Node::BinaryExpr {
+                        kind: BinaryExprKind::Add,
+                        lhs: Box::new(Node::Integer(1)),
+                        rhs: Box::new(Node::Integer(2)),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
                 },
             ),
             (
                 "rand(1, 6)",
                 Node::FunctionCall {
-                    name: "rand".to_string(),
-                    parameters: vec![Box::new(Node::Integer(1)), Box::new(Node::Integer(6))],
+                    ident: "rand".to_string(),
+                    parameters: vec![Node::Integer(1), Node::Integer(6)],
+                    span: Span::default(),
+                },
+            ),
+            (
+                "rand()",
+                Node::FunctionCall {
+                    ident: "rand".to_string(),
+                    parameters: Vec::new(),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "max(1D6, min(2, 3))",
+                Node::FunctionCall {
+                    ident: "max".to_string(),
+                    parameters: vec![
+                        Node::InfixCall {
+                            ident: "D".to_string(),
+                            lhs: Box::new(Node::Integer(1)),
+                            rhs: Box::new(Node::Integer(6)),
+                            span: Span::default(),
+                        },
+                        Node::FunctionCall {
+                            ident: "min".to_string(),
+                            parameters: vec![Node::Integer(2), Node::Integer(3)],
+                            span: Span::default(),
+                        },
+                    ],
+                    span: Span::default(),
+                },
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            assert_eq!(parser.parse().unwrap(), Box::new(expected));
+        }
+    }
+
+    #[test]
+    fn statement_parse_test() {
+        let tests = [
+            (
+                "let STR = 3D6",
+                Statement::Let {
+                    name: "STR".to_string(),
+                    expr: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(3)),
+                        rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
+                    }),
+                },
+            ),
+            (
+                "def CCB => 1D100",
+                Statement::Def(CommandDefinition::new(
+                    "CCB".to_string(),
+                    CommandKind::Nullary,
+                    Vec::new(),
+                    Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(1)),
+                        rhs: Box::new(Node::Integer(100)),
+                        span: Span::default(),
+                    }),
+                )),
+            ),
+            (
+                r#"def n"R"m => n*m"#,
+                Statement::Def(CommandDefinition::new(
+                    "R".to_string(),
+                    CommandKind::Infix,
+                    vec![
+                        Parameter::new("n".to_string()),
+                        Parameter::new("m".to_string()),
+                    ],
+                    Box::new(Node::BinaryExpr {
+                        kind: BinaryExprKind::Mul,
+                        lhs: Box::new(Node::NullaryCall {
+                            ident: "n".to_string(),
+                            span: Span::default(),
+                        }),
+                        rhs: Box::new(Node::NullaryCall {
+                            ident: "m".to_string(),
+                            span: Span::default(),
+                        }),
+                        span: Span::default(),
+                    }),
+                )),
+            ),
+            (
+                r#"def "neg"x => 0-x"#,
+                Statement::Def(CommandDefinition::new(
+                    "neg".to_string(),
+                    CommandKind::Prefix,
+                    vec![Parameter::new("x".to_string())],
+                    Box::new(Node::BinaryExpr {
+                        kind: BinaryExprKind::Sub,
+                        lhs: Box::new(Node::Integer(0)),
+                        rhs: Box::new(Node::NullaryCall {
+                            ident: "x".to_string(),
+                            span: Span::default(),
+                        }),
+                        span: Span::default(),
+                    }),
+                )),
+            ),
+            (
+                r#"def x"H" => x/2"#,
+                Statement::Def(CommandDefinition::new(
+                    "H".to_string(),
+                    CommandKind::Postfix,
+                    vec![Parameter::new("x".to_string())],
+                    Box::new(Node::BinaryExpr {
+                        kind: BinaryExprKind::Div,
+                        lhs: Box::new(Node::NullaryCall {
+                            ident: "x".to_string(),
+                            span: Span::default(),
+                        }),
+                        rhs: Box::new(Node::Integer(2)),
+                        span: Span::default(),
+                    }),
+                )),
+            ),
+            (
+                r#"def ~n"R"m => n"#,
+                Statement::Def(CommandDefinition::new(
+                    "R".to_string(),
+                    CommandKind::Infix,
+                    vec![
+                        Parameter::lazy("n".to_string()),
+                        Parameter::new("m".to_string()),
+                    ],
+                    Box::new(Node::NullaryCall {
+                        ident: "n".to_string(),
+                        span: Span::default(),
+                    }),
+                )),
+            ),
+            (
+                r#"def "twice"~x => x"#,
+                Statement::Def(CommandDefinition::new(
+                    "twice".to_string(),
+                    CommandKind::Prefix,
+                    vec![Parameter::lazy("x".to_string())],
+                    Box::new(Node::NullaryCall {
+                        ident: "x".to_string(),
+                        span: Span::default(),
+                    }),
+                )),
+            ),
+            (
+                "1D6",
+                Statement::Expr(Box::new(Node::InfixCall {
+                    ident: "D".to_string(),
+                    lhs: Box::new(Node::Integer(1)),
+                    rhs: Box::new(Node::Integer(6)),
+                    span: Span::default(),
+                })),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            assert_eq!(parser.parse_statement().unwrap(), expected);
+        }
+
+        let inputs = [
+            "let = 1",
+            "let x 1",
+            "def x = 1",
+            r#"def "D" => 1"#,
+            "def ~x => 1",
+            "def x\"H\"~ => 1",
+        ];
+
+        for input in inputs {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            assert!(parser.parse_statement().is_err());
+        }
+    }
+
+    #[test]
+    fn program_parse_test() {
+        let input = "\nCCB => 1D100\n\nn\"R\"m => rand(n, m)\n\"H\"x => x/2\nx\"U\" => x*2";
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program().unwrap();
+        let defs = program
+            .defs()
+            .iter()
+            .map(|d| (d.name(), d.kind(), d.parameters().to_vec()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            defs,
+            [
+                ("CCB", CommandKind::Nullary, vec![]),
+                (
+                    "R",
+                    CommandKind::Infix,
+                    vec![
+                        Parameter::new("n".to_string()),
+                        Parameter::new("m".to_string())
+                    ]
+                ),
+                (
+                    "H",
+                    CommandKind::Prefix,
+                    vec![Parameter::new("x".to_string())]
+                ),
+                (
+                    "U",
+                    CommandKind::Postfix,
+                    vec![Parameter::new("x".to_string())]
+                ),
+            ]
+        );
+        assert_eq!(
+            program.defs()[1].expr(),
+            &Node::FunctionCall {
+                ident: "rand".to_string(),
+                parameters: vec![
+                    Node::NullaryCall {
+                        ident: "n".to_string(),
+                        span: Span::default(),
+                    },
+                    Node::NullaryCall {
+                        ident: "m".to_string(),
+                        span: Span::default(),
+                    },
+                ],
+                span: Span::default(),
+            }
+        );
+
+        let tests = [
+            ("CCB => 1D100 2", "expected newline, found '2'", (13, 14)),
+            ("CCB = 1", "expected '=>', found '='", (4, 5)),
+            ("CCB => (1", "expected ')', found end of input", (9, 10)),
+            ("\"D\" => 1", "expected identifier, found '=>'", (4, 6)),
+            (
+                "\"y\"b => b +\nCCB => 1",
+                "expected number or '(', found end of line",
+                (11, 12),
+            ),
+        ];
+
+        for (input, expected, (start, end)) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let error = parser.parse_program().unwrap_err();
+
+            assert_eq!(error.to_string(), expected);
+            assert_eq!(error.span(), Span::new(start, end));
+        }
+    }
+
+    #[test]
+    fn dice_modifier_parse_test() {
+        let tests = [
+            (
+                "4D6KH3",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::KeepHighest,
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(4)),
+                        rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::Integer(3))),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "2d20kl",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::KeepLowest,
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "d".to_string(),
+                        lhs: Box::new(Node::Integer(2)),
+                        rhs: Box::new(Node::Integer(20)),
+                        span: Span::default(),
+                    }),
+                    rhs: None,
+                    span: Span::default(),
+                },
+            ),
+            (
+                "5D6dl1DH(1+1)",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::DropHighest,
+                    lhs: Box::new(Node::DiceModifier {
+                        kind: DiceModifierKind::DropLowest,
+                        lhs: Box::new(Node::InfixCall {
+                            ident: "D".to_string(),
+                            lhs: Box::new(Node::Integer(5)),
+                            rhs: Box::new(Node::Integer(6)),
+                            span: Span::default(),
+                        }),
+                        rhs: Some(Box::new(Node::Integer(1))),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::BinaryExpr {
+                        kind: BinaryExprKind::Add,
+                        lhs: Box::new(Node::Integer(1)),
+                        rhs: Box::new(Node::Integer(1)),
+                        span: Span::default(),
+                    })),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "3D6!",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::Explode(ComparisonExprKind::Eq),
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(3)),
+                        rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
+                    }),
+                    rhs: None,
+                    span: Span::default(),
+                },
+            ),
+            (
+                "1D10!>=9",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::Explode(ComparisonExprKind::Ge),
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(1)),
+                        rhs: Box::new(Node::Integer(10)),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::Integer(9))),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "2D6!!5",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::Compound(ComparisonExprKind::Eq),
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(2)),
+                        rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::Integer(5))),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "2D6!p<2KH1",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::KeepHighest,
+                    lhs: Box::new(Node::DiceModifier {
+                        kind: DiceModifierKind::Penetrate(ComparisonExprKind::Lt),
+                        lhs: Box::new(Node::InfixCall {
+                            ident: "D".to_string(),
+                            lhs: Box::new(Node::Integer(2)),
+                            rhs: Box::new(Node::Integer(6)),
+                            span: Span::default(),
+                        }),
+                        rhs: Some(Box::new(Node::Integer(2))),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::Integer(1))),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "4D6r1",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::Reroll(ComparisonExprKind::Eq),
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(4)),
+                        rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::Integer(1))),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "4D6ro<2",
+                Node::DiceModifier {
+                    kind: DiceModifierKind::RerollOnce(ComparisonExprKind::Lt),
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
+                        lhs: Box::new(Node::Integer(4)),
+                        rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
+                    }),
+                    rhs: Some(Box::new(Node::Integer(2))),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "4D6KH3+2",
+                Node::BinaryExpr {
+                    kind: BinaryExprKind::Add,
+                    lhs: Box::new(Node::DiceModifier {
+                        kind: DiceModifierKind::KeepHighest,
+                        lhs: Box::new(Node::InfixCall {
+                            ident: "D".to_string(),
+                            lhs: Box::new(Node::Integer(4)),
+                            rhs: Box::new(Node::Integer(6)),
+                            span: Span::default(),
+                        }),
+                        rhs: Some(Box::new(Node::Integer(3))),
+                        span: Span::default(),
+                    }),
+                    rhs: Box::new(Node::Integer(2)),
+                    span: Span::default(),
+                },
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            assert_eq!(parser.parse().unwrap(), Box::new(expected));
+        }
+
+        // 修飾子に続く "!=" は振り足しと条件の "="。空白を挟めば比較になる
+        let tests = [
+            ("1D6!=6", "1D6!6"),
+            ("2D6!!=5", "2D6!!5"),
+            ("1D6 != 6", "1D6<>6"),
+        ];
+
+        for (input, expected) in tests {
+            let parse = |input: &str| Parser::new(Lexer::new(input.to_string())).parse();
+
+            assert_eq!(parse(input), parse(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn error_parse_test() {
+        let tests = [
+            (
+                "1D6+*2",
+                ParseErrorKind::UnexpectedToken {
+                    expected: "number or '('".to_string(),
+                    found: "*".to_string(),
+                },
+                (4, 5),
+            ),
+            (
+                "(1+2",
+                ParseErrorKind::UnexpectedEof {
+                    expected: "')'".to_string(),
+                },
+                (4, 5),
+            ),
+            (
+                "1+2)",
+                ParseErrorKind::UnexpectedToken {
+                    expected: "end of input".to_string(),
+                    found: ")".to_string(),
+                },
+                (3, 4),
+            ),
+            (
+                "1&2",
+                ParseErrorKind::IllegalCharacter("&".to_string()),
+                (1, 2),
+            ),
+            (
+                "1000000000000000000000000000000000000000000",
+                ParseErrorKind::NumberOutOfRange(
+                    "1000000000000000000000000000000000000000000".to_string(),
+                ),
+                (0, 43),
+            ),
+            (
+                "max(1, )",
+                ParseErrorKind::UnexpectedToken {
+                    expected: "number or '('".to_string(),
+                    found: ")".to_string(),
+                },
+                (7, 8),
+            ),
+            (
+                "rand(1, 6",
+                ParseErrorKind::UnexpectedEof {
+                    expected: "')'".to_string(),
+                },
+                (9, 10),
+            ),
+        ];
+
+        for (input, expected, (start, end)) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let error = parser.parse().unwrap_err();
+
+            assert_eq!(error.kind(), &expected);
+            assert_eq!(error.span(), Span::new(start, end));
+        }
+
+        let tests = [
+            format!("{}1{}", "(".repeat(5000), ")".repeat(5000)),
+            format!("{}1D6", "-".repeat(100000)),
+            format!("{}1{}", "abs(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING)),
+        ];
+
+        for input in tests {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+
+            assert_eq!(
+                parser.parse().unwrap_err().kind(),
+                &ParseErrorKind::NestingTooDeep(MAX_NESTING)
+            );
+        }
+
+        let input = format!("{}1{}", "(".repeat(99), ")".repeat(99));
+
+        assert!(Parser::new(Lexer::new(input)).parse().is_ok());
+
+        let error = Parser::new(Lexer::new("1D6\n+(2*".to_string()))
+            .parse()
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "expected number or '(', found end of input"
+        );
+        assert_eq!(error.span(), Span::new(8, 9));
+    }
+
+    #[test]
+    fn display_parse_test() {
+        let inputs = [
+            "1+2*3",
+            "(1+2)*3",
+            "1-(2-3)",
+            "1-2-3",
+            "(1+2)D6",
+            "1D(2*(1+2))",
+            "4D6KH3",
+            "5D6DL1DH(1+1)",
+            "3D6!",
+            "1D10!>=9",
+            "2D6!!5",
+            "2D6!p<2KL1",
+            "4D6r1",
+            "4D6ro<2",
+            "10B6>=5",
+            "(1=1)<>(1=2)",
+            "CCB<=50",
+            "sum(3B6)",
+            "d6",
+            "2d",
+            "rand(1, 6)",
+            "adv(1+2)",
+            "-3",
+            "1D20+-2",
+            "1D6*-1",
+            "-2D6",
+            "-(1+2)",
+            "(-2)D6",
+            "max(-1, 2)",
+        ];
+
+        for input in inputs {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+
+            assert_eq!(parser.parse().unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn unary_parse_test() {
+        let dice = |count, faces| {
+            Box::new(Node::InfixCall {
+                ident: "D".to_string(),
+                lhs: Box::new(Node::Integer(count)),
+                rhs: Box::new(Node::Integer(faces)),
+                span: Span::default(),
+            })
+        };
+        let tests = [
+            ("-3", Node::Integer(-3)),
+            ("+3", Node::Integer(3)),
+            ("--3", Node::Integer(3)),
+            (
+                "-2D6",
+                Node::Negation {
+                    operand: dice(2, 6),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "-(2D6)",
+                Node::Negation {
+                    operand: dice(2, 6),
+                    span: Span::default(),
+                },
+            ),
+            ("+2D6", *dice(2, 6)),
+            (
+                "1D20+-2",
+                Node::BinaryExpr {
+                    kind: BinaryExprKind::Add,
+                    lhs: dice(1, 20),
+                    rhs: Box::new(Node::Integer(-2)),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "1D6*-1",
+                Node::BinaryExpr {
+                    kind: BinaryExprKind::Mul,
+                    lhs: dice(1, 6),
+                    rhs: Box::new(Node::Integer(-1)),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "-2*3",
+                Node::BinaryExpr {
+                    kind: BinaryExprKind::Mul,
+                    lhs: Box::new(Node::Integer(-2)),
+                    rhs: Box::new(Node::Integer(3)),
+                    span: Span::default(),
+                },
+            ),
+            (
+                "2--3",
+                Node::BinaryExpr {
+                    kind: BinaryExprKind::Sub,
+                    lhs: Box::new(Node::Integer(2)),
+                    rhs: Box::new(Node::Integer(-3)),
+                    span: Span::default(),
+                },
+            ),
+            ("(-2)D6", *dice(-2, 6)),
+            (
+                "-CCB",
+                Node::Negation {
+                    operand: Box::new(Node::NullaryCall {
+                        ident: "CCB".to_string(),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
                 },
             ),
         ];
@@ -356,7 +1374,7 @@ mod test {
             let lexer = Lexer::new(input.to_string());
             let mut parser = Parser::new(lexer);
 
-            assert_eq!(parser.expr().unwrap(), Box::new(expected));
+            assert_eq!(parser.parse().unwrap(), Box::new(expected), "{}", input);
         }
     }
 
@@ -369,7 +1387,7 @@ mod test {
             let mut parser = Parser::new(lexer);
 
             assert_eq!(
-                parser.expr().unwrap(),
+                parser.parse().unwrap(),
                 Box::new(Node::Integer(input.parse::<IntegerType>().unwrap()))
             )
         }
@@ -386,6 +1404,7 @@ mod test {
                         kind: BinaryExprKind::Add,
                         lhs: Box::new(Node::Integer(1)),
                         rhs: Box::new(Node::Integer(2)),
+                        span: Span::default(),
                     }),
                     rhs: Box::new(Node::BinaryExpr {
                         kind: BinaryExprKind::Div,
@@ -393,9 +1412,12 @@ mod test {
                             kind: BinaryExprKind::Mul,
                             lhs: Box::new(Node::Integer(3)),
                             rhs: Box::new(Node::Integer(4)),
+                            span: Span::default(),
                         }),
                         rhs: Box::new(Node::Integer(5)),
+                        span: Span::default(),
                     }),
+                    span: Span::default(),
                 },
             ),
             (
@@ -409,13 +1431,17 @@ mod test {
                             kind: BinaryExprKind::Mul,
                             lhs: Box::new(Node::Integer(2)),
                             rhs: Box::new(Node::Integer(3)),
+                            span: Span::default(),
                         }),
+                        span: Span::default(),
                     }),
                     rhs: Box::new(Node::BinaryExpr {
                         kind: BinaryExprKind::Div,
                         lhs: Box::new(Node::Integer(4)),
                         rhs: Box::new(Node::Integer(5)),
+                        span: Span::default(),
                     }),
+                    span: Span::default(),
                 },
             ),
             (
@@ -432,28 +1458,34 @@ mod test {
                                 kind: BinaryExprKind::Sub,
                                 lhs: Box::new(Node::Integer(3)),
                                 rhs: Box::new(Node::Integer(4)),
+                                span: Span::default(),
                             }),
+                            span: Span::default(),
                         }),
                         rhs: Box::new(Node::Integer(5)),
+                        span: Span::default(),
                     }),
+                    span: Span::default(),
                 },
             ),
             (
                 "(1+2)D6",
-                Node::InfixCommand {
-                    name: "D".to_string(),
+                Node::InfixCall {
+                    ident: "D".to_string(),
                     lhs: Box::new(Node::BinaryExpr {
                         kind: BinaryExprKind::Add,
                         lhs: Box::new(Node::Integer(1)),
                         rhs: Box::new(Node::Integer(2)),
+                        span: Span::default(),
                     }),
                     rhs: Box::new(Node::Integer(6)),
+                    span: Span::default(),
                 },
             ),
             (
                 "1D(2*(1+2))",
-                Node::InfixCommand {
-                    name: "D".to_string(),
+                Node::InfixCall {
+                    ident: "D".to_string(),
                     lhs: Box::new(Node::Integer(1)),
                     rhs: Box::new(Node::BinaryExpr {
                         kind: BinaryExprKind::Mul,
@@ -462,20 +1494,25 @@ mod test {
                             kind: BinaryExprKind::Add,
                             lhs: Box::new(Node::Integer(1)),
                             rhs: Box::new(Node::Integer(2)),
+                            span: Span::default(),
                         }),
+                        span: Span::default(),
                     }),
+                    span: Span::default(),
                 },
             ),
             (
                 "3D6+3",
                 Node::BinaryExpr {
                     kind: BinaryExprKind::Add,
-                    lhs: Box::new(Node::InfixCommand {
-                        name: "D".to_string(),
+                    lhs: Box::new(Node::InfixCall {
+                        ident: "D".to_string(),
                         lhs: Box::new(Node::Integer(3)),
                         rhs: Box::new(Node::Integer(6)),
+                        span: Span::default(),
                     }),
                     rhs: Box::new(Node::Integer(3)),
+                    span: Span::default(),
                 },
             ),
             (
@@ -486,8 +1523,10 @@ mod test {
                         kind: BinaryExprKind::Add,
                         lhs: Box::new(Node::Integer(1)),
                         rhs: Box::new(Node::Integer(2)),
+                        span: Span::default(),
                     }),
                     rhs: Box::new(Node::Integer(3)),
+                    span: Span::default(),
                 },
             ),
             (
@@ -496,6 +1535,7 @@ mod test {
                     kind: ComparisonExprKind::Eq,
                     lhs: Box::new(Node::Integer(2)),
                     rhs: Box::new(Node::Integer(2)),
+                    span: Span::default(),
                 },
             ),
             (
@@ -504,6 +1544,7 @@ mod test {
                     kind: ComparisonExprKind::Ne,
                     lhs: Box::new(Node::Integer(2)),
                     rhs: Box::new(Node::Integer(2)),
+                    span: Span::default(),
                 },
             ),
             (
@@ -512,6 +1553,7 @@ mod test {
                     kind: ComparisonExprKind::Ne,
                     lhs: Box::new(Node::Integer(2)),
                     rhs: Box::new(Node::Integer(2)),
+                    span: Span::default(),
                 },
             ),
             (
@@ -520,14 +1562,19 @@ mod test {
                     kind: ComparisonExprKind::Lt,
                     lhs: Box::new(Node::Integer(10)),
                     rhs: Box::new(Node::Integer(12)),
+                    span: Span::default(),
                 },
             ),
             (
                 "CCB<=100",
                 Node::ComparisonExpr {
                     kind: ComparisonExprKind::Le,
-                    lhs: Box::new(Node::NullaryCommand("CCB".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "CCB".to_string(),
+                        span: Span::default(),
+                    }),
                     rhs: Box::new(Node::Integer(100)),
+                    span: Span::default(),
                 },
             ),
             (
@@ -536,14 +1583,19 @@ mod test {
                     kind: ComparisonExprKind::Gt,
                     lhs: Box::new(Node::Integer(10)),
                     rhs: Box::new(Node::Integer(12)),
+                    span: Span::default(),
                 },
             ),
             (
                 "CCB>=10",
                 Node::ComparisonExpr {
                     kind: ComparisonExprKind::Ge,
-                    lhs: Box::new(Node::NullaryCommand("CCB".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "CCB".to_string(),
+                        span: Span::default(),
+                    }),
                     rhs: Box::new(Node::Integer(10)),
+                    span: Span::default(),
                 },
             ),
         ];
@@ -552,7 +1604,7 @@ mod test {
             let lexer = Lexer::new(input.to_string());
             let mut parser = Parser::new(lexer);
 
-            assert_eq!(parser.expr().unwrap(), Box::new(expected));
+            assert_eq!(parser.parse().unwrap(), Box::new(expected));
         }
     }
 }
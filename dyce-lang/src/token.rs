@@ -1,6 +1,7 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TokenKind {
-    Illegal,     // 解析できないトークン
+    #[default]
+    Illegal, // 解析できないトークン
     Number,      // 数字
     Plus,        // +
     Minus,       // -
@@ -8,11 +9,13 @@ pub enum TokenKind {
     Slash,       // /
     Eq,          // = ==
     Ne,          // != <>
+    Bang,        // !
     Lt,          // <
     Le,          // <=
     Gt,          // >
     Ge,          // >=
     Comma,       // ,
+    Tilde,       // ~
     DoubleQuote, // "
     LParen,      // (
     RParen,      // )
@@ -20,20 +23,81 @@ pub enum TokenKind {
     Separator,   // 定義を区切るための改行群
     Arrow,       // =>
 }
-impl Default for TokenKind {
-    fn default() -> Self {
-        Self::Illegal
+
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                TokenKind::Illegal => "illegal character",
+                TokenKind::Number => "number",
+                TokenKind::Plus => "'+'",
+                TokenKind::Minus => "'-'",
+                TokenKind::Asterisk => "'*'",
+                TokenKind::Slash => "'/'",
+                TokenKind::Eq => "'='",
+                TokenKind::Ne => "'<>'",
+                TokenKind::Bang => "'!'",
+                TokenKind::Lt => "'<'",
+                TokenKind::Le => "'<='",
+                TokenKind::Gt => "'>'",
+                TokenKind::Ge => "'>='",
+                TokenKind::Comma => "','",
+                TokenKind::Tilde => "'~'",
+                TokenKind::DoubleQuote => "'\"'",
+                TokenKind::LParen => "'('",
+                TokenKind::RParen => "')'",
+                TokenKind::Ident => "identifier",
+                TokenKind::Separator => "newline",
+                TokenKind::Arrow => "'=>'",
+            }
+        )
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+// 入力中の文字単位の位置。start 文字目から end 文字目の手前まで
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    start: usize,
+    end: usize,
+}
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    // self から other までを覆う範囲
+    pub fn to(&self, other: Span) -> Self {
+        Self::new(self.start, other.end.max(self.start))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct Token {
     kind: TokenKind,
     literal: String,
+    span: Span,
 }
 impl Token {
     pub fn new(kind: TokenKind, literal: String) -> Self {
-        Self { kind, literal }
+        Self {
+            kind,
+            literal,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Self { span, ..self }
     }
 
     pub fn kind(&self) -> TokenKind {
@@ -43,4 +107,17 @@ impl Token {
     pub fn literal(&self) -> String {
         self.literal.clone()
     }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
+
+// 位置は比較しない
+impl PartialEq for Token {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.literal == other.literal
+    }
+}
+
+impl Eq for Token {}
@@ -1,44 +1,1169 @@
 use crate::{
-    ast::Node,
+    ast::{ComparisonExprKind, DiceModifierKind, Node, PRIMARY_PRECEDENCE},
     environment::{Environment, FunctionForm, FunctionKind},
-    eval::eval,
-    object::Object,
+    error::{Error, ErrorKind},
+    eval::{compare, eval},
+    object::{DiceTerm, Evaluation, Object},
     IntegerType,
 };
-use rand::{seq::IteratorRandom, thread_rng};
+use serde::Serialize;
+use std::sync::Arc;
 
-pub fn eval_builtin(
-    key: &FunctionForm,
-    parameters: Vec<Box<Node>>,
-    env: &Environment,
-) -> Option<Object> {
-    match key.name() {
-        "D" | "d" => {
-            if key.kind() == &FunctionKind::Infix {
-                let count = eval(parameters.iter().nth(0)?.clone(), env).ok()?;
-                let kind = eval(parameters.iter().nth(1)?.clone(), env).ok()?;
-
-                if let Object::Integer(count) = count {
-                    if let Object::Integer(kind) = kind {
-                        return Some(Object::Integer(roll(count, kind)?));
-                    }
+pub const REROLL_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Die {
+    value: IntegerType,
+    faces: IntegerType,
+    dropped: bool,
+}
+impl Die {
+    pub fn new(value: IntegerType, faces: IntegerType) -> Self {
+        Self {
+            value,
+            faces,
+            dropped: false,
+        }
+    }
+
+    pub fn value(&self) -> IntegerType {
+        self.value
+    }
+
+    pub fn faces(&self) -> IntegerType {
+        self.faces
+    }
+
+    pub fn is_dropped(&self) -> bool {
+        self.dropped
+    }
+}
+
+impl std::fmt::Display for Die {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.dropped {
+            write!(f, "~~{}~~", self.value)
+        } else {
+            write!(f, "{}", self.value)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    AtLeast(usize),
+}
+impl Arity {
+    pub fn check(&self, form: &FunctionForm, found: usize) -> Result<(), Error> {
+        let expected = match *self {
+            Arity::Exact(n) if found != n => n,
+            Arity::AtLeast(n) if found < n => n,
+            _ => return Ok(()),
+        };
+
+        Err(Error::new(ErrorKind::ArityMismatch {
+            form: form.clone(),
+            expected,
+            found,
+        }))
+    }
+}
+
+// Rust で実装する組み込みの命令。Environment::register で登録する
+pub trait Builtin: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn kind(&self) -> FunctionKind;
+
+    fn arity(&self) -> Arity;
+
+    // 評価済みの引数に適用する。引数の個数は arity に合うことを確かめてから呼ばれる
+    fn eval(&self, args: Vec<Object>, env: &Environment) -> Result<Object, Error>;
+
+    // REPL の :help で表示する説明
+    fn help(&self) -> &str {
+        ""
+    }
+
+    // 引数だけで値が決まるなら、確率分布を引数の値ごとに eval を呼んで求められる
+    fn is_deterministic(&self) -> bool {
+        false
+    }
+
+    // 引数の値を与えたときに取りうる値とその確率。None なら確率分布は試行で近似する
+    fn outcomes(
+        &self,
+        args: Vec<Object>,
+        env: &Environment,
+    ) -> Result<Option<Vec<(IntegerType, f64)>>, Error> {
+        if !self.is_deterministic() {
+            return Ok(None);
+        }
+
+        Ok(match self.eval(args, env)? {
+            Object::Integer(v) => Some(vec![(v, 1.0)]),
+            Object::Boolean(v) => Some(vec![(v as IntegerType, 1.0)]),
+            _ => None,
+        })
+    }
+
+    // 修飾子や確率分布は一様なダイスを直接振って求めるので、組み込みの D と B だけが上書きできる
+    #[doc(hidden)]
+    fn is_dice(&self, _: sealed::Token) -> bool {
+        false
+    }
+
+    // ダイスを合計せずにダイスプールのまま返すか
+    #[doc(hidden)]
+    fn is_pool(&self, _: sealed::Token) -> bool {
+        false
+    }
+
+    // ダイスプールを一つ受け取ったときにプールから求める値
+    fn aggregate(&self) -> Option<Aggregate> {
+        None
+    }
+
+    // trace や振ったダイスを自分で組み立てる場合に上書きする
+    fn eval_traced(&self, args: Vec<Evaluation>, env: &Environment) -> Result<Evaluation, Error> {
+        let dice = args
+            .iter()
+            .flat_map(|a| a.dice().to_vec())
+            .collect::<Vec<_>>();
+        let traces = args
+            .iter()
+            .map(|a| a.operand_trace(PRIMARY_PRECEDENCE, false))
+            .collect::<Vec<_>>();
+        let trace = match (self.kind(), traces.as_slice()) {
+            (FunctionKind::Nullary, _) => self.name().to_string(),
+            (FunctionKind::Infix, [lhs, rhs]) => format!("{}{}{}", lhs, self.name(), rhs),
+            (FunctionKind::Postfix, [lhs]) => format!("{}{}", lhs, self.name()),
+            _ => format!(
+                "{}({})",
+                self.name(),
+                args.iter()
+                    .map(|a| a.trace())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        let args = args.into_iter().map(Evaluation::into_object).collect();
+
+        Ok(Evaluation::new(self.eval(args, env)?, trace).with_dice(dice))
+    }
+
+    fn form(&self) -> FunctionForm {
+        FunctionForm::new(self.name().to_string(), self.kind())
+    }
+}
+
+// クレートの外からは名前を書けないので、この型を引数に取るメソッドは上書きできない
+mod sealed {
+    pub struct Token;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Max,
+    Min,
+    Len,
+}
+
+// Environment::new で登録される組み込みの命令
+pub fn standard() -> Vec<Arc<dyn Builtin>> {
+    let dice: [(&str, bool, &str); 4] = [
+        (
+            "D",
+            false,
+            "nDm rolls n m-sided dice and sums them, e.g. 2D6, 4D6kh3, 3D6!, 4D6r1",
+        ),
+        ("d", false, "same as D"),
+        (
+            "B",
+            true,
+            "nBm rolls n m-sided dice and keeps them as a pool, e.g. 5B6>=5",
+        ),
+        ("b", true, "same as B"),
+    ];
+    let functions: [(&str, Arity, &str); 11] = [
+        (
+            "sum",
+            Arity::AtLeast(0),
+            "sum(POOL) adds up the dice in a pool, sum(a, b, ...) adds up its arguments",
+        ),
+        (
+            "max",
+            Arity::AtLeast(0),
+            "max(POOL) is the highest die in a pool, max(a, b, ...) the highest argument",
+        ),
+        (
+            "min",
+            Arity::AtLeast(0),
+            "min(POOL) is the lowest die in a pool, min(a, b, ...) the lowest argument",
+        ),
+        (
+            "sort",
+            Arity::AtLeast(0),
+            "sort(POOL) sorts a pool in ascending order",
+        ),
+        (
+            "len",
+            Arity::AtLeast(0),
+            "len(POOL) counts the dice in a pool",
+        ),
+        (
+            "count",
+            Arity::Exact(2),
+            "count(POOL, n) counts the dice in a pool showing n",
+        ),
+        ("abs", Arity::Exact(1), "abs(x) is the absolute value of x"),
+        (
+            "clamp",
+            Arity::Exact(3),
+            "clamp(x, lo, hi) limits x to the range lo..hi",
+        ),
+        (
+            "floor",
+            Arity::Exact(2),
+            "floor(a, b) divides a by b rounding down",
+        ),
+        (
+            "ceil",
+            Arity::Exact(2),
+            "ceil(a, b) divides a by b rounding up",
+        ),
+        (
+            "round",
+            Arity::Exact(2),
+            "round(a, b) divides a by b rounding to the nearest integer, halves away from zero",
+        ),
+    ];
+
+    let mut builtins = Vec::<Arc<dyn Builtin>>::new();
+
+    for (name, is_pool, help) in dice {
+        builtins.push(Arc::new(Dice {
+            name,
+            is_pool,
+            help,
+        }));
+    }
+
+    for (name, arity, help) in functions {
+        builtins.push(Arc::new(Function { name, arity, help }));
+    }
+
+    builtins.push(Arc::new(Rand));
+
+    builtins
+}
+
+// D と B。修飾子の付いた nDm は eval_dice が直接振る
+struct Dice {
+    name: &'static str,
+    is_pool: bool,
+    help: &'static str,
+}
+impl Builtin for Dice {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn kind(&self) -> FunctionKind {
+        FunctionKind::Infix
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::Exact(2)
+    }
+
+    fn eval(&self, args: Vec<Object>, env: &Environment) -> Result<Object, Error> {
+        let [count, faces] = arguments(&self.form(), args)?;
+        let dice = roll(integer(count)?, integer(faces)?, env)?;
+
+        Ok(if self.is_pool {
+            Object::List(values(&dice))
+        } else {
+            Object::Integer(total(&dice)?)
+        })
+    }
+
+    fn help(&self) -> &str {
+        self.help
+    }
+
+    fn is_dice(&self, _: sealed::Token) -> bool {
+        true
+    }
+
+    fn is_pool(&self, _: sealed::Token) -> bool {
+        self.is_pool
+    }
+
+    fn eval_traced(&self, args: Vec<Evaluation>, env: &Environment) -> Result<Evaluation, Error> {
+        let dice = args
+            .iter()
+            .flat_map(|a| a.dice().to_vec())
+            .collect::<Vec<_>>();
+        let [count, faces] = arguments(&self.form(), args)?;
+        let (count, faces) = (integer(count.into_object())?, integer(faces.into_object())?);
+        let expr = format!("{}{}{}", count, self.name, faces);
+        let evaluation = dice_evaluation(expr, roll(count, faces, env)?, self.is_pool)?;
+        let dice = [dice, evaluation.dice().to_vec()].concat();
+
+        Ok(evaluation.with_dice(dice))
+    }
+}
+
+// 乱数を使わない関数。name(a, b, ...) の形で呼び出し、引数が一つなら前置の命令と同じ
+struct Function {
+    name: &'static str,
+    arity: Arity,
+    help: &'static str,
+}
+impl Builtin for Function {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn kind(&self) -> FunctionKind {
+        FunctionKind::Prefix
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn eval(&self, args: Vec<Object>, _: &Environment) -> Result<Object, Error> {
+        apply(&self.form(), args)
+    }
+
+    fn help(&self) -> &str {
+        self.help
+    }
+
+    fn is_deterministic(&self) -> bool {
+        true
+    }
+
+    fn aggregate(&self) -> Option<Aggregate> {
+        match self.name {
+            "sum" => Some(Aggregate::Sum),
+            "max" => Some(Aggregate::Max),
+            "min" => Some(Aggregate::Min),
+            "len" => Some(Aggregate::Len),
+            _ => None,
+        }
+    }
+}
+
+// rand は出た値そのものを trace とする
+struct Rand;
+impl Builtin for Rand {
+    fn name(&self) -> &str {
+        "rand"
+    }
+
+    fn kind(&self) -> FunctionKind {
+        FunctionKind::Prefix
+    }
+
+    fn arity(&self) -> Arity {
+        Arity::Exact(2)
+    }
+
+    fn eval(&self, args: Vec<Object>, env: &Environment) -> Result<Object, Error> {
+        let (min, max) = rand_range(&self.form(), args)?;
+
+        Ok(Object::Integer(min + env.roll(max - min + 1)? - 1))
+    }
+
+    fn help(&self) -> &str {
+        "rand(a, b) picks an integer from a to b uniformly"
+    }
+
+    // 幅はダイスの面数と同じ上限まで扱う
+    fn outcomes(
+        &self,
+        args: Vec<Object>,
+        env: &Environment,
+    ) -> Result<Option<Vec<(IntegerType, f64)>>, Error> {
+        let (min, max) = rand_range(&self.form(), args)?;
+
+        if max - min >= env.limits().max_faces {
+            return Ok(None);
+        }
+
+        let n = (max - min + 1) as f64;
+
+        Ok(Some((min..=max).map(|v| (v, 1.0 / n)).collect()))
+    }
+
+    fn eval_traced(&self, args: Vec<Evaluation>, env: &Environment) -> Result<Evaluation, Error> {
+        let dice = args
+            .iter()
+            .flat_map(|a| a.dice().to_vec())
+            .collect::<Vec<_>>();
+        let object = self.eval(args.into_iter().map(Evaluation::into_object).collect(), env)?;
+
+        Ok(Evaluation::new(object.clone(), object.to_string()).with_dice(dice))
+    }
+}
+
+fn arguments<T, const N: usize>(key: &FunctionForm, parameters: Vec<T>) -> Result<[T; N], Error> {
+    parameters.try_into().map_err(|p: Vec<T>| {
+        Error::new(ErrorKind::ArityMismatch {
+            form: key.clone(),
+            expected: N,
+            found: p.len(),
+        })
+    })
+}
+
+// rand(a, b) は a 以上 b 以下の整数を一様に選ぶ
+fn rand_range(key: &FunctionForm, args: Vec<Object>) -> Result<(IntegerType, IntegerType), Error> {
+    let [min, max] = arguments(key, args)?;
+    let (min, max) = (integer(min)?, integer(max)?);
+
+    if min > max {
+        return Err(Error::new(ErrorKind::InvalidArgument(format!(
+            "rand({}, {}) has an empty range",
+            min, max
+        ))));
+    }
+
+    if max
+        .checked_sub(min)
+        .and_then(|d| d.checked_add(1))
+        .is_none()
+    {
+        return Err(Error::new(ErrorKind::Overflow));
+    }
+
+    Ok((min, max))
+}
+
+// 乱数を使わない関数を評価済みの引数に適用する
+fn apply(key: &FunctionForm, args: Vec<Object>) -> Result<Object, Error> {
+    let object = match key.name() {
+        "abs" => {
+            let [x] = arguments(key, args)?;
+
+            Object::Integer(
+                integer(x)?
+                    .checked_abs()
+                    .ok_or_else(|| Error::new(ErrorKind::Overflow))?,
+            )
+        }
+        "clamp" => {
+            let [x, min, max] = arguments(key, args)?;
+            let (x, min, max) = (integer(x)?, integer(min)?, integer(max)?);
+
+            if min > max {
+                return Err(Error::new(ErrorKind::InvalidArgument(format!(
+                    "clamp to {}..{} has an empty range",
+                    min, max
+                ))));
+            }
+
+            Object::Integer(x.clamp(min, max))
+        }
+        name @ ("floor" | "ceil" | "round") => {
+            let [lhs, rhs] = arguments(key, args)?;
+
+            Object::Integer(divide(name, integer(lhs)?, integer(rhs)?)?)
+        }
+        "count" => {
+            let [list, target] = arguments(key, args)?;
+            let target = integer(target)?;
+
+            Object::Integer(
+                self::list(vec![list])?
+                    .into_iter()
+                    .filter(|&v| v == target)
+                    .count() as IntegerType,
+            )
+        }
+        name => {
+            let mut list = list(args)?;
+            let empty = || {
+                Error::new(ErrorKind::InvalidArgument(format!(
+                    "{} of an empty list",
+                    name
+                )))
+            };
+
+            match name {
+                "sum" => Object::Integer(sum(&list)?),
+                "max" => Object::Integer(*list.iter().max().ok_or_else(empty)?),
+                "min" => Object::Integer(*list.iter().min().ok_or_else(empty)?),
+                "sort" => {
+                    list.sort();
+
+                    Object::List(list)
                 }
+                _ => Object::Integer(list.len() as IntegerType),
+            }
+        }
+    };
 
-                return None;
+    Ok(object)
+}
+
+fn integer(object: Object) -> Result<IntegerType, Error> {
+    match object {
+        Object::Integer(v) => Ok(v),
+        object => Err(Error::new(ErrorKind::TypeMismatch {
+            expected: "integer".to_string(),
+            found: object.type_name().to_string(),
+        })),
+    }
+}
+
+// 整数とダイスプールを並べて一つの列にする
+fn list(args: Vec<Object>) -> Result<Vec<IntegerType>, Error> {
+    let mut list = Vec::new();
+
+    for arg in args {
+        match arg {
+            Object::List(v) => list.extend(v),
+            Object::Integer(v) => list.push(v),
+            object => {
+                return Err(Error::new(ErrorKind::TypeMismatch {
+                    expected: "list".to_string(),
+                    found: object.type_name().to_string(),
+                }))
             }
         }
-        _ => {}
     }
 
-    None
+    Ok(list)
+}
+
+// 割り切れないとき floor は負の無限大へ、ceil は正の無限大へ、round は近い方へ丸める。ちょうど半分なら 0 から遠い方
+fn divide(name: &str, lhs: IntegerType, rhs: IntegerType) -> Result<IntegerType, Error> {
+    if rhs == 0 {
+        return Err(Error::new(ErrorKind::DivisionByZero));
+    }
+
+    let quotient = lhs
+        .checked_div(rhs)
+        .ok_or_else(|| Error::new(ErrorKind::Overflow))?;
+    let remainder = lhs % rhs;
+
+    if remainder == 0 {
+        return Ok(quotient);
+    }
+
+    let negative = (remainder < 0) != (rhs < 0);
+    let away = match name {
+        "floor" => negative,
+        "ceil" => !negative,
+        _ => remainder.unsigned_abs() >= rhs.unsigned_abs() - remainder.unsigned_abs(),
+    };
+
+    Ok(match (away, negative) {
+        (false, _) => quotient,
+        (true, true) => quotient - 1,
+        (true, false) => quotient + 1,
+    })
+}
+
+pub fn eval_dice(node: &Node, env: &Environment) -> Result<Evaluation, Error> {
+    let is_pool = is_pool(node, env);
+    let expr = node.to_string();
+
+    dice_evaluation(expr, dice(node, env)?, is_pool)
+}
+
+// D は合計と出目の一覧 "7[4,3]"、B は出目の一覧 "[4,3]" を trace とする
+fn dice_evaluation(expr: String, dice: Vec<Die>, is_pool: bool) -> Result<Evaluation, Error> {
+    let faces = format!(
+        "[{}]",
+        dice.iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+
+    let evaluation = if is_pool {
+        Evaluation::new(Object::List(values(&dice)), faces)
+    } else {
+        let total = total(&dice)?;
+
+        Evaluation::new(Object::Integer(total), format!("{}{}", total, faces))
+    };
+
+    Ok(evaluation.with_dice(vec![DiceTerm::new(expr, dice)]))
+}
+
+// 修飾子を付けられるのは組み込みのダイスだけ。利用者の定義で隠されていれば付けられない
+pub fn is_dice(ident: &str, env: &Environment) -> bool {
+    dice_builtin(ident, env).is_some()
+}
+
+pub fn is_pool(node: &Node, env: &Environment) -> bool {
+    match node {
+        Node::InfixCall { ident, .. } => {
+            dice_builtin(ident, env).is_some_and(|b| b.is_pool(sealed::Token))
+        }
+        Node::DiceModifier { lhs, .. } => is_pool(lhs, env),
+        _ => false,
+    }
+}
+
+fn dice_builtin<'a>(ident: &str, env: &'a Environment) -> Option<&'a dyn Builtin> {
+    env.builtin(&FunctionForm::new(ident.to_string(), FunctionKind::Infix))
+        .filter(|b| b.is_dice(sealed::Token))
 }
 
-fn roll(count: IntegerType, kind: IntegerType) -> Option<IntegerType> {
-    if count < 0 || kind < 1 {
-        return None;
+fn dice(node: &Node, env: &Environment) -> Result<Vec<Die>, Error> {
+    match node {
+        Node::InfixCall {
+            ident, lhs, rhs, ..
+        } if is_dice(ident, env) => roll_dice(lhs, rhs, env),
+        Node::DiceModifier { kind, lhs, rhs, .. } => {
+            let dice = dice(lhs, env)?;
+            let rhs = match rhs {
+                Some(rhs) => Some(eval_integer(rhs, env)?),
+                None => None,
+            };
+
+            modify(dice, *kind, rhs, env)
+        }
+        node => Err(Error::new(ErrorKind::InvalidArgument(format!(
+            "{} is not a dice roll",
+            node
+        )))),
     }
+}
+
+fn eval_integer(node: &Node, env: &Environment) -> Result<IntegerType, Error> {
+    integer(eval(node, env)?.into_object())
+}
+
+fn roll_dice(count: &Node, kind: &Node, env: &Environment) -> Result<Vec<Die>, Error> {
+    roll(eval_integer(count, env)?, eval_integer(kind, env)?, env)
+}
+
+fn roll(count: IntegerType, kind: IntegerType, env: &Environment) -> Result<Vec<Die>, Error> {
+    env.limits().check_dice(count, kind)?;
 
     (0..count)
-        .map(|_| (1..=kind).choose(&mut thread_rng()))
-        .sum()
+        .map(|_| Ok(Die::new(env.roll(kind)?, kind)))
+        .collect()
+}
+
+fn values(dice: &[Die]) -> Vec<IntegerType> {
+    dice.iter()
+        .filter(|d| !d.is_dropped())
+        .map(|d| d.value())
+        .collect()
+}
+
+fn total(dice: &[Die]) -> Result<IntegerType, Error> {
+    sum(&values(dice))
+}
+
+fn sum(values: &[IntegerType]) -> Result<IntegerType, Error> {
+    values
+        .iter()
+        .try_fold(0 as IntegerType, |acc, &v| acc.checked_add(v))
+        .ok_or_else(|| Error::new(ErrorKind::Overflow))
+}
+
+fn modify(
+    mut dice: Vec<Die>,
+    kind: DiceModifierKind,
+    rhs: Option<IntegerType>,
+    env: &Environment,
+) -> Result<Vec<Die>, Error> {
+    match kind {
+        DiceModifierKind::KeepHighest
+        | DiceModifierKind::KeepLowest
+        | DiceModifierKind::DropHighest
+        | DiceModifierKind::DropLowest => {
+            let count = rhs.unwrap_or(1);
+
+            if count < 0 {
+                return Err(Error::new(ErrorKind::InvalidArgument(format!(
+                    "{}{} with a negative count",
+                    kind, count
+                ))));
+            }
+
+            let remaining = remaining_indices(&dice).len() as IntegerType;
+            let count = count.min(remaining);
+
+            match kind {
+                DiceModifierKind::KeepHighest => drop_lowest(&mut dice, remaining - count),
+                DiceModifierKind::KeepLowest => drop_highest(&mut dice, remaining - count),
+                DiceModifierKind::DropHighest => drop_highest(&mut dice, count),
+                _ => drop_lowest(&mut dice, count),
+            }
+
+            Ok(dice)
+        }
+        DiceModifierKind::Explode(comparison) => explode(dice, comparison, rhs, 0, env),
+        DiceModifierKind::Penetrate(comparison) => explode(dice, comparison, rhs, 1, env),
+        DiceModifierKind::Compound(comparison) => compound(dice, comparison, rhs, env),
+        DiceModifierKind::Reroll(comparison) => reroll(dice, comparison, rhs, false, env),
+        DiceModifierKind::RerollOnce(comparison) => reroll(dice, comparison, rhs, true, env),
+    }
+}
+
+// 条件を満たした出目ごとにダイスを追加で振る。penalty は追加ダイスの出目から引く値
+fn explode(
+    dice: Vec<Die>,
+    comparison: ComparisonExprKind,
+    target: Option<IntegerType>,
+    penalty: IntegerType,
+    env: &Environment,
+) -> Result<Vec<Die>, Error> {
+    let mut result = Vec::new();
+
+    for die in dice {
+        let target = target.unwrap_or(die.faces());
+        let mut face = die.value();
+        let mut rerolls = 0;
+
+        result.push(die);
+
+        if die.is_dropped() {
+            continue;
+        }
+
+        check_explosion(comparison, target, die.faces())?;
+
+        while compare(comparison, face, target) && rerolls < REROLL_LIMIT {
+            face = env.roll(die.faces())?;
+            rerolls += 1;
+
+            result.push(Die::new(face - penalty, die.faces()));
+        }
+    }
+
+    Ok(result)
+}
+
+// 条件を満たした出目ごとに振り足し、元のダイスの出目に加算する
+fn compound(
+    mut dice: Vec<Die>,
+    comparison: ComparisonExprKind,
+    target: Option<IntegerType>,
+    env: &Environment,
+) -> Result<Vec<Die>, Error> {
+    for die in dice.iter_mut().filter(|d| !d.is_dropped()) {
+        let target = target.unwrap_or(die.faces());
+        let mut face = die.value();
+        let mut rerolls = 0;
+
+        check_explosion(comparison, target, die.faces())?;
+
+        while compare(comparison, face, target) && rerolls < REROLL_LIMIT {
+            face = env.roll(die.faces())?;
+            rerolls += 1;
+
+            die.value = die
+                .value
+                .checked_add(face)
+                .ok_or_else(|| Error::new(ErrorKind::Overflow))?;
+        }
+    }
+
+    Ok(dice)
+}
+
+// 全ての出目が条件を満たすと振り足しが終わらない
+fn check_explosion(
+    comparison: ComparisonExprKind,
+    target: IntegerType,
+    faces: IntegerType,
+) -> Result<(), Error> {
+    if matches_every_face(comparison, target, faces) {
+        return Err(Error::new(ErrorKind::InvalidArgument(format!(
+            "every face of a D{} explodes",
+            faces
+        ))));
+    }
+
+    Ok(())
+}
+
+// 条件を満たした出目を振り直す。振り直す前のダイスは除外済みとして直前に残す
+fn reroll(
+    dice: Vec<Die>,
+    comparison: ComparisonExprKind,
+    target: Option<IntegerType>,
+    once: bool,
+    env: &Environment,
+) -> Result<Vec<Die>, Error> {
+    let target = target.unwrap_or(1);
+    let mut result = Vec::new();
+
+    for mut die in dice {
+        // 全ての出目が条件を満たすと振り直しが終わらない
+        if !once && !die.is_dropped() && matches_every_face(comparison, target, die.faces()) {
+            return Err(Error::new(ErrorKind::InvalidArgument(format!(
+                "every face of a D{} is rerolled",
+                die.faces()
+            ))));
+        }
+
+        let mut rerolls = 0;
+
+        while !die.is_dropped() && compare(comparison, die.value(), target) {
+            if rerolls >= REROLL_LIMIT {
+                break;
+            }
+
+            result.push(Die {
+                dropped: true,
+                ..die
+            });
+            die = Die::new(env.roll(die.faces())?, die.faces());
+            rerolls += 1;
+
+            if once {
+                break;
+            }
+        }
+
+        result.push(die);
+    }
+
+    Ok(result)
+}
+
+// 1 から faces までの出目が全て条件を満たすか
+pub(crate) fn matches_every_face(
+    comparison: ComparisonExprKind,
+    target: IntegerType,
+    faces: IntegerType,
+) -> bool {
+    match comparison {
+        ComparisonExprKind::Ne => !(1..=faces).contains(&target),
+        _ => compare(comparison, 1, target) && compare(comparison, faces, target),
+    }
+}
+
+fn drop_lowest(dice: &mut [Die], count: IntegerType) {
+    let mut indices = remaining_indices(dice);
+
+    indices.sort_by_key(|&i| dice[i].value());
+
+    for i in indices.into_iter().take(count as usize) {
+        dice[i].dropped = true;
+    }
+}
+
+fn drop_highest(dice: &mut [Die], count: IntegerType) {
+    let mut indices = remaining_indices(dice);
+
+    indices.sort_by_key(|&i| std::cmp::Reverse(dice[i].value()));
+
+    for i in indices.into_iter().take(count as usize) {
+        dice[i].dropped = true;
+    }
+}
+
+fn remaining_indices(dice: &[Die]) -> Vec<usize> {
+    (0..dice.len()).filter(|&i| !dice[i].is_dropped()).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{eval::exec, lexer::Lexer, parser::Parser, random::ScriptedRandom};
+
+    // 引数に 1 を足す後置の命令
+    struct Succ;
+    impl Builtin for Succ {
+        fn name(&self) -> &str {
+            "S"
+        }
+
+        fn kind(&self) -> FunctionKind {
+            FunctionKind::Postfix
+        }
+
+        fn arity(&self) -> Arity {
+            Arity::Exact(1)
+        }
+
+        fn eval(&self, args: Vec<Object>, _: &Environment) -> Result<Object, Error> {
+            let [x] = arguments(&self.form(), args)?;
+
+            Ok(Object::Integer(integer(x)? + 1))
+        }
+    }
+
+    // ダイスを振らずに掛け算をする中置の命令
+    struct Times;
+    impl Builtin for Times {
+        fn name(&self) -> &str {
+            "D"
+        }
+
+        fn kind(&self) -> FunctionKind {
+            FunctionKind::Infix
+        }
+
+        fn arity(&self) -> Arity {
+            Arity::Exact(2)
+        }
+
+        fn eval(&self, args: Vec<Object>, _: &Environment) -> Result<Object, Error> {
+            let [lhs, rhs] = arguments(&self.form(), args)?;
+
+            Ok(Object::Integer(integer(lhs)? * integer(rhs)?))
+        }
+    }
+
+    #[test]
+    fn register_test() {
+        let run = |input: &str, env: &mut Environment| {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let statement = parser.parse_statement().unwrap();
+
+            exec(statement.clone(), env).map(|r| r.map(|r| r.describe(statement.expr())))
+        };
+        let succ = FunctionForm::new("S".to_string(), FunctionKind::Postfix);
+        let dice = FunctionForm::new("D".to_string(), FunctionKind::Infix);
+        let mut env = Environment::with_random(ScriptedRandom::new(vec![3, 4]));
+
+        assert_eq!(
+            run("2S", &mut env).unwrap_err().kind(),
+            &ErrorKind::UnknownCommand(succ.clone())
+        );
+        assert!(env.register(Succ).is_none());
+        assert_eq!(
+            run("(1D6)S", &mut env).unwrap().unwrap(),
+            "((1D6)S) ＞ 3[3]S ＞ 4"
+        );
+        assert!(env.is_builtin(&succ));
+
+        // 利用者の定義は組み込みを隠し、組み込みの登録はそのまま残る
+        run(r#"def x"S" => x*10"#, &mut env).unwrap();
+
+        assert_eq!(run("2S", &mut env).unwrap().unwrap(), "(2S) ＞ 2*10 ＞ 20");
+        assert!(!env.is_builtin(&succ));
+        assert!(env.builtins().any(|b| b.form() == succ));
+
+        assert!(env.unregister(&dice).is_some());
+        assert_eq!(
+            run("1D6", &mut env).unwrap_err().kind(),
+            &ErrorKind::UnknownCommand(dice.clone())
+        );
+        assert!(env.unregister(&dice).is_none());
+
+        // ダイスとして振る組み込みでなければ修飾子は付けられない
+        env.register(Times);
+
+        assert_eq!(run("2D6", &mut env).unwrap().unwrap(), "(2D6) ＞ 12");
+        assert_eq!(
+            run("2D6KH1", &mut env).unwrap_err().kind(),
+            &ErrorKind::InvalidArgument("2D6 is not a dice roll".to_string())
+        );
+        assert_eq!(
+            Arity::AtLeast(2).check(&dice, 1).unwrap_err().kind(),
+            &ErrorKind::ArityMismatch {
+                form: dice,
+                expected: 2,
+                found: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn modify_test() {
+        let tests = [
+            (DiceModifierKind::KeepHighest, 3, 13),
+            (DiceModifierKind::KeepHighest, 1, 6),
+            (DiceModifierKind::KeepHighest, 9, 14),
+            (DiceModifierKind::KeepLowest, 1, 1),
+            (DiceModifierKind::KeepLowest, 2, 4),
+            (DiceModifierKind::DropHighest, 1, 8),
+            (DiceModifierKind::DropLowest, 1, 13),
+            (DiceModifierKind::DropLowest, 4, 0),
+        ];
+
+        for (kind, count, expected) in tests {
+            let dice = [3, 6, 1, 4].map(|v| Die::new(v, 6)).to_vec();
+            let dice = modify(dice, kind, Some(count), &Environment::new()).unwrap();
+
+            assert_eq!(total(&dice).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn explode_test() {
+        let env = Environment::with_random(ScriptedRandom::new(vec![]));
+        let limit = REROLL_LIMIT as IntegerType;
+        let tests = [
+            (
+                DiceModifierKind::Explode(ComparisonExprKind::Eq),
+                3 * (1 + REROLL_LIMIT),
+                3 * 6 * (1 + limit),
+            ),
+            (
+                DiceModifierKind::Penetrate(ComparisonExprKind::Eq),
+                3 * (1 + REROLL_LIMIT),
+                3 * 6 + 3 * 5 * limit,
+            ),
+            (
+                DiceModifierKind::Compound(ComparisonExprKind::Eq),
+                3,
+                3 * 6 * (1 + limit),
+            ),
+        ];
+
+        for (kind, len, value) in tests {
+            let dice = modify(vec![Die::new(6, 6); 3], kind, None, &env).unwrap();
+
+            assert_eq!(dice.len(), len);
+            assert_eq!(total(&dice).unwrap(), value);
+        }
+
+        // 全ての出目が条件を満たすと振り足しが終わらない
+        let tests = [
+            (DiceModifierKind::Explode(ComparisonExprKind::Ge), 1, 6),
+            (DiceModifierKind::Penetrate(ComparisonExprKind::Eq), 1, 1),
+            (DiceModifierKind::Compound(ComparisonExprKind::Ne), 0, 6),
+        ];
+
+        for (kind, target, faces) in tests {
+            let result = modify(vec![Die::new(1, faces)], kind, Some(target), &env);
+
+            assert_eq!(
+                result.unwrap_err().kind(),
+                &ErrorKind::InvalidArgument(format!("every face of a D{} explodes", faces))
+            );
+        }
+
+        // 振り足しの上限はダイスごとに数える
+        let count = REROLL_LIMIT + 1;
+        let tests = [
+            (
+                DiceModifierKind::Explode(ComparisonExprKind::Eq),
+                2 * count,
+                7,
+            ),
+            (DiceModifierKind::Compound(ComparisonExprKind::Eq), count, 7),
+        ];
+
+        for (kind, len, value) in tests {
+            let env = Environment::with_random(ScriptedRandom::new(vec![1]));
+            let dice = modify(vec![Die::new(6, 6); count], kind, None, &env).unwrap();
+
+            assert_eq!(dice.len(), len);
+            assert_eq!(total(&dice).unwrap(), value * count as IntegerType);
+        }
+
+        let dice = vec![Die::new(5, 6), Die::new(6, 6)];
+        let dice = modify(
+            dice,
+            DiceModifierKind::Explode(ComparisonExprKind::Gt),
+            Some(6),
+            &Environment::new(),
+        )
+        .unwrap();
+
+        assert_eq!(total(&dice).unwrap(), 11);
+
+        let tests = [
+            (
+                DiceModifierKind::Explode(ComparisonExprKind::Eq),
+                "[6,6,2,3]",
+            ),
+            (
+                DiceModifierKind::Penetrate(ComparisonExprKind::Eq),
+                "[6,5,1,3]",
+            ),
+            (DiceModifierKind::Compound(ComparisonExprKind::Eq), "[14,3]"),
+        ];
+
+        for (kind, expected) in tests {
+            let env = Environment::with_random(ScriptedRandom::new(vec![6, 2]));
+            let dice = vec![Die::new(6, 6), Die::new(3, 6)];
+            let dice = modify(dice, kind, None, &env).unwrap();
+
+            assert_eq!(
+                dice_evaluation(String::new(), dice, true).unwrap().trace(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn reroll_test() {
+        // 振り直しの上限はダイスごとに数える
+        let count = REROLL_LIMIT + 1;
+        let env = Environment::with_random(ScriptedRandom::new(vec![5]));
+        let dice = modify(
+            vec![Die::new(2, 6); count],
+            DiceModifierKind::Reroll(ComparisonExprKind::Le),
+            Some(3),
+            &env,
+        )
+        .unwrap();
+
+        assert_eq!(dice.len(), 2 * count);
+        assert_eq!(dice.iter().filter(|d| d.is_dropped()).count(), count);
+        assert_eq!(total(&dice).unwrap(), 5 * count as IntegerType);
+
+        // 全ての出目が条件を満たすと振り直しが終わらない
+        let tests = [
+            (ComparisonExprKind::Le, 6, 6),
+            (ComparisonExprKind::Ge, 1, 6),
+            (ComparisonExprKind::Eq, 1, 1),
+            (ComparisonExprKind::Ne, 7, 6),
+        ];
+
+        for (comparison, target, faces) in tests {
+            let dice = vec![Die::new(1, faces)];
+            let result = modify(
+                dice,
+                DiceModifierKind::Reroll(comparison),
+                Some(target),
+                &Environment::new(),
+            );
+
+            assert_eq!(
+                result.unwrap_err().kind(),
+                &ErrorKind::InvalidArgument(format!("every face of a D{} is rerolled", faces))
+            );
+        }
+
+        let dice = vec![Die::new(1, 6), Die::new(2, 2)];
+        let dice = modify(
+            dice,
+            DiceModifierKind::RerollOnce(ComparisonExprKind::Le),
+            Some(6),
+            &Environment::with_random(ScriptedRandom::new(vec![])),
+        )
+        .unwrap();
+
+        assert_eq!(dice.len(), 4);
+        assert_eq!(total(&dice).unwrap(), 8);
+
+        let dice = vec![Die::new(1, 1), Die::new(2, 2), Die::new(1, 1)];
+        let dice = modify(
+            dice,
+            DiceModifierKind::RerollOnce(ComparisonExprKind::Lt),
+            Some(2),
+            &Environment::new(),
+        )
+        .unwrap();
+
+        assert_eq!(dice.len(), 5);
+        assert!(dice[0].is_dropped());
+        assert!(!dice[1].is_dropped());
+        assert!(!dice[2].is_dropped());
+        assert!(dice[3].is_dropped());
+        assert_eq!(total(&dice).unwrap(), 4);
+    }
 }
@@ -0,0 +1,918 @@
+use crate::{
+    ast::{BinaryExprKind, ComparisonExprKind, DiceModifierKind, Node},
+    builtin::{is_dice, is_pool, matches_every_face, Aggregate, Builtin, REROLL_LIMIT},
+    environment::{Environment, Function, FunctionForm, FunctionKind},
+    error::{Error, ErrorKind},
+    eval::{arithmetic, compare},
+    lexer::Lexer,
+    object::Object,
+    parser::Parser,
+    IntegerType,
+};
+use std::collections::{BTreeMap, HashMap};
+
+// これより小さい確率の振り足しは f64 では区別できない
+const EPSILON: f64 = 1e-15;
+const BAR_WIDTH: f64 = 40.0;
+// 一つの分布を求めるために組み合わせる値の組の数の上限。超える式は試行で近似する
+const MAX_WORK: usize = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+    probabilities: BTreeMap<IntegerType, f64>,
+}
+impl Distribution {
+    fn point(value: IntegerType) -> Self {
+        Self::from_pairs([(value, 1.0)])
+    }
+
+    fn uniform(faces: IntegerType) -> Self {
+        Self::from_pairs((1..=faces).map(|v| (v, 1.0 / faces as f64)))
+    }
+
+    // 比較の結果は真を 1、偽を 0 とする
+    fn boolean(p: f64) -> Self {
+        Self::from_pairs([(0, 1.0 - p), (1, p)])
+    }
+
+    fn from_pairs(pairs: impl IntoIterator<Item = (IntegerType, f64)>) -> Self {
+        let mut probabilities = BTreeMap::new();
+
+        for (value, p) in pairs {
+            if p > 0.0 {
+                *probabilities.entry(value).or_insert(0.0) += p;
+            }
+        }
+
+        Self { probabilities }
+    }
+
+    fn mix(parts: impl IntoIterator<Item = (f64, Distribution)>) -> Self {
+        Self::from_pairs(parts.into_iter().flat_map(|(p, distribution)| {
+            distribution
+                .probabilities
+                .into_iter()
+                .map(move |(v, q)| (v, p * q))
+        }))
+    }
+
+    pub fn probability(&self, value: IntegerType) -> f64 {
+        self.probabilities.get(&value).copied().unwrap_or(0.0)
+    }
+
+    pub fn probabilities(&self) -> &BTreeMap<IntegerType, f64> {
+        &self.probabilities
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IntegerType, f64)> + '_ {
+        self.probabilities.iter().map(|(&v, &p)| (v, p))
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.iter().map(|(v, p)| v as f64 * p).sum()
+    }
+
+    fn constant(&self) -> Option<IntegerType> {
+        match self.probabilities.len() {
+            1 => self.probabilities.keys().next().copied(),
+            _ => None,
+        }
+    }
+
+    fn satisfy(&self, comparison: ComparisonExprKind, target: IntegerType) -> f64 {
+        self.iter()
+            .filter(|&(v, _)| compare(comparison, v, target))
+            .map(|(_, p)| p)
+            .sum()
+    }
+
+    fn combine(
+        &self,
+        other: &Distribution,
+        f: impl Fn(IntegerType, IntegerType) -> Result<IntegerType, Error>,
+    ) -> Result<Self, Error> {
+        let mut pairs = Vec::new();
+
+        for (lhs, p) in self.iter() {
+            for (rhs, q) in other.iter() {
+                pairs.push((f(lhs, rhs)?, p * q));
+            }
+        }
+
+        Ok(Self::from_pairs(pairs))
+    }
+
+    fn too_large(&self, other: &Distribution) -> bool {
+        self.probabilities
+            .len()
+            .saturating_mul(other.probabilities.len())
+            > MAX_WORK
+    }
+
+    fn add(&self, other: &Distribution) -> Option<Self> {
+        if self.too_large(other) {
+            return None;
+        }
+
+        Some(Self::from_pairs(self.iter().flat_map(|(lhs, p)| {
+            other.iter().map(move |(rhs, q)| (lhs + rhs, p * q))
+        })))
+    }
+
+    // count 個の独立な試行の和
+    fn sum_of(&self, count: IntegerType) -> Option<Self> {
+        let mut result: Option<Self> = None;
+        let mut base = self.clone();
+        let mut count = count;
+
+        while count > 0 {
+            if count & 1 == 1 {
+                result = Some(match result {
+                    Some(result) => result.add(&base)?,
+                    None => base.clone(),
+                });
+            }
+
+            count >>= 1;
+
+            if count > 0 {
+                base = base.add(&base)?;
+            }
+        }
+
+        Some(result.unwrap_or_else(|| Self::point(0)))
+    }
+
+    // 条件を満たした出目にだけ tail を足す。offset は出目そのものに加える値
+    fn chain(
+        &self,
+        comparison: ComparisonExprKind,
+        target: IntegerType,
+        offset: IntegerType,
+        tail: &Distribution,
+    ) -> Self {
+        Self::from_pairs(self.iter().flat_map(|(face, p)| {
+            if compare(comparison, face, target) {
+                tail.iter()
+                    .map(|(v, q)| (face + offset + v, p * q))
+                    .collect::<Vec<_>>()
+            } else {
+                vec![(face + offset, p)]
+            }
+        }))
+    }
+}
+
+impl std::fmt::Display for Distribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .probabilities
+            .keys()
+            .map(|v| v.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let max = self.probabilities.values().fold(0.0, |a: f64, &b| a.max(b));
+
+        for (i, (value, p)) in self.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(
+                f,
+                "{:>width$} {:>8.4}% {}",
+                value,
+                p * 100.0,
+                "#".repeat((p / max * BAR_WIDTH).round() as usize),
+                width = width
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// 同じ面数のダイスを count 個振った結果。keep は (大きい方から残すか, 残す個数)
+struct Pool {
+    weight: f64,
+    count: IntegerType,
+    faces: IntegerType,
+    die: Distribution,
+    keep: Option<(bool, IntegerType)>,
+    exploded: bool,
+}
+impl Pool {
+    fn modify(self, kind: DiceModifierKind, rhs: Option<IntegerType>) -> Option<Self> {
+        // 除外や振り足しの後の修飾はダイス同士の並びに依存するため扱わない
+        if self.keep.is_some() || self.exploded {
+            return None;
+        }
+
+        let die = match kind {
+            DiceModifierKind::KeepHighest
+            | DiceModifierKind::KeepLowest
+            | DiceModifierKind::DropHighest
+            | DiceModifierKind::DropLowest => {
+                let count = rhs.unwrap_or(1);
+
+                if count < 0 {
+                    return None;
+                }
+
+                let count = count.min(self.count);
+                let keep = match kind {
+                    DiceModifierKind::KeepHighest => (true, count),
+                    DiceModifierKind::KeepLowest => (false, count),
+                    DiceModifierKind::DropHighest => (false, self.count - count),
+                    _ => (true, self.count - count),
+                };
+
+                return Some(Self {
+                    keep: Some(keep),
+                    ..self
+                });
+            }
+            DiceModifierKind::Explode(comparison) => {
+                return Some(Self {
+                    die: self.explode(comparison, rhs.unwrap_or(self.faces), 0)?,
+                    exploded: true,
+                    ..self
+                });
+            }
+            DiceModifierKind::Penetrate(comparison) => {
+                return Some(Self {
+                    die: self.explode(comparison, rhs.unwrap_or(self.faces), 1)?,
+                    exploded: true,
+                    ..self
+                });
+            }
+            DiceModifierKind::Compound(comparison) => {
+                self.explode(comparison, rhs.unwrap_or(self.faces), 0)?
+            }
+            DiceModifierKind::Reroll(comparison) => {
+                let target = rhs.unwrap_or(1);
+
+                // 振り直しが終わらない条件は試行のほうで誤りとして報告する
+                if matches_every_face(comparison, target, self.faces) {
+                    return None;
+                }
+
+                self.reroll(comparison, target, false)
+            }
+            DiceModifierKind::RerollOnce(comparison) => {
+                self.reroll(comparison, rhs.unwrap_or(1), true)
+            }
+        };
+
+        Some(Self { die, ..self })
+    }
+
+    // ダイス 1 個と、そこから振り足したダイスの合計の分布。振り足しが終わらない条件は試行に任せる
+    fn explode(
+        &self,
+        comparison: ComparisonExprKind,
+        target: IntegerType,
+        penalty: IntegerType,
+    ) -> Option<Distribution> {
+        if matches_every_face(comparison, target, self.faces) {
+            return None;
+        }
+
+        let roll = Distribution::uniform(self.faces);
+        let continued = roll.satisfy(comparison, target);
+        let depth = if continued < 1.0 {
+            REROLL_LIMIT.min((EPSILON.ln() / continued.ln()).ceil() as usize)
+        } else {
+            REROLL_LIMIT
+        };
+        let mut tail = Distribution::point(0);
+
+        for _ in 0..depth {
+            if roll.too_large(&tail) {
+                return None;
+            }
+
+            tail = roll.chain(comparison, target, -penalty, &tail);
+        }
+
+        if self.die.too_large(&tail) {
+            return None;
+        }
+
+        Some(self.die.chain(comparison, target, 0, &tail))
+    }
+
+    fn reroll(
+        &self,
+        comparison: ComparisonExprKind,
+        target: IntegerType,
+        once: bool,
+    ) -> Distribution {
+        let roll = Distribution::uniform(self.faces);
+        let rejected = roll.satisfy(comparison, target);
+        // 何度でも振り直す場合は条件を満たさない出目が出るまで続く
+        let replacement = if once || rejected >= 1.0 {
+            roll
+        } else {
+            Distribution::from_pairs(
+                roll.iter()
+                    .filter(|&(v, _)| !compare(comparison, v, target))
+                    .map(|(v, p)| (v, p / (1.0 - rejected))),
+            )
+        };
+        let rerolled = self.die.satisfy(comparison, target);
+
+        Distribution::from_pairs(
+            self.die
+                .iter()
+                .filter(|&(v, _)| !compare(comparison, v, target))
+                .chain(replacement.iter().map(|(v, p)| (v, rerolled * p))),
+        )
+    }
+
+    fn total(&self) -> Option<Distribution> {
+        let (highest, keep) = match self.keep {
+            Some(keep) => keep,
+            None => return self.die.sum_of(self.count),
+        };
+        let count = self.count as usize;
+        let keep = keep as usize;
+        let mut faces = self.die.iter().collect::<Vec<_>>();
+
+        if highest {
+            faces.reverse();
+        }
+
+        // 残す側の出目から順に、その出目になったダイスの個数ごとに場合分けする
+        let mut states = BTreeMap::from([((0, 0), 1.0)]);
+
+        for (face, p) in faces {
+            let mut next = BTreeMap::new();
+
+            if states.len().saturating_mul(count + 1) > MAX_WORK {
+                return None;
+            }
+
+            for ((assigned, sum), q) in states {
+                let rest = count - assigned;
+                let mut ways = q;
+
+                for c in 0..=rest {
+                    let kept = c.min(keep.saturating_sub(assigned)) as IntegerType;
+
+                    *next.entry((assigned + c, sum + kept * face)).or_insert(0.0) += ways;
+                    ways *= (rest - c) as f64 / (c + 1) as f64 * p;
+                }
+            }
+
+            states = next;
+        }
+
+        Some(Distribution::from_pairs(
+            states
+                .into_iter()
+                .filter(|&((assigned, _), _)| assigned == count)
+                .map(|((_, sum), p)| (sum, p)),
+        ))
+    }
+
+    fn successes(
+        &self,
+        comparison: ComparisonExprKind,
+        target: IntegerType,
+    ) -> Option<Distribution> {
+        if self.keep.is_some() || self.exploded {
+            return None;
+        }
+
+        Distribution::boolean(self.die.satisfy(comparison, target)).sum_of(self.count)
+    }
+
+    fn aggregate(&self, aggregate: Aggregate) -> Option<Distribution> {
+        match aggregate {
+            Aggregate::Sum => self.total(),
+            Aggregate::Len if !self.exploded => Some(Distribution::point(
+                self.keep.map_or(self.count, |(_, keep)| keep),
+            )),
+            Aggregate::Max | Aggregate::Min
+                if !self.exploded && self.keep.is_none() && self.count > 0 =>
+            {
+                let mut faces = self.die.iter().collect::<Vec<_>>();
+
+                if aggregate == Aggregate::Min {
+                    faces.reverse();
+                }
+
+                // 最大値が v 以下である確率は全てのダイスが v 以下である確率
+                let mut cumulative = 0.0;
+                let mut previous = 0.0;
+
+                Some(Distribution::from_pairs(faces.into_iter().map(|(v, p)| {
+                    cumulative += p;
+
+                    let current = f64::powf(cumulative, self.count as f64);
+                    let p = current - previous;
+
+                    previous = current;
+
+                    (v, p)
+                })))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn analyze(input: &str, env: &Environment) -> Result<Distribution, Error> {
+    let lexer = Lexer::new(input.to_string());
+    let mut parser = Parser::new(lexer);
+    let node = parser.parse()?;
+
+    distribution(&node, env)
+}
+
+fn unsupported(node: &Node) -> Error {
+    let error = Error::new(ErrorKind::Unsupported(node.to_string()));
+
+    match node.span() {
+        Some(span) => error.with_span(span),
+        None => error,
+    }
+}
+
+// 手数は評価と同じ上限で数える
+pub fn distribution(node: &Node, env: &Environment) -> Result<Distribution, Error> {
+    let _nesting = env.enter()?;
+
+    distribution_node(node, env)
+}
+
+fn distribution_node(node: &Node, env: &Environment) -> Result<Distribution, Error> {
+    match node {
+        Node::Integer(v) => Ok(Distribution::point(*v)),
+        Node::BinaryExpr { kind, lhs, rhs, .. } => {
+            let lhs = distribution(lhs, env)?;
+            let rhs = distribution(rhs, env)?;
+
+            if lhs.too_large(&rhs) {
+                return Err(unsupported(node));
+            }
+
+            lhs.combine(&rhs, |lhs, rhs| arithmetic(*kind, lhs, rhs))
+        }
+        Node::Negation { operand, .. } if !is_pool(operand, env) => distribution(operand, env)?
+            .combine(&Distribution::point(0), |v, zero| {
+                arithmetic(BinaryExprKind::Sub, zero, v)
+            }),
+        Node::ComparisonExpr { kind, lhs, rhs, .. } => {
+            let target = distribution(rhs, env)?;
+
+            // ダイスプールとの比較は条件を満たすダイスの個数
+            if is_pool(lhs, env) {
+                let pools = pools(lhs, env)?;
+                let mut parts = Vec::new();
+
+                for (target, p) in target.iter() {
+                    for pool in &pools {
+                        parts.push((
+                            p * pool.weight,
+                            pool.successes(*kind, target)
+                                .ok_or_else(|| unsupported(node))?,
+                        ));
+                    }
+                }
+
+                return Ok(Distribution::mix(parts));
+            }
+
+            let lhs = distribution(lhs, env)?;
+
+            if lhs.too_large(&target) {
+                return Err(unsupported(node));
+            }
+
+            Ok(Distribution::boolean(
+                lhs.iter()
+                    .flat_map(|(lhs, p)| {
+                        target
+                            .iter()
+                            .filter(move |&(rhs, _)| compare(*kind, lhs, rhs))
+                            .map(move |(_, q)| p * q)
+                    })
+                    .sum(),
+            ))
+        }
+        Node::InfixCall { ident, .. } if is_dice(ident, env) && !is_pool(node, env) => {
+            total(node, env)
+        }
+        Node::InfixCall { ident, .. } if is_dice(ident, env) => Err(unsupported(node)),
+        Node::DiceModifier { .. } if !is_pool(node, env) => total(node, env),
+        Node::NullaryCall { ident, .. } => call(
+            node,
+            FunctionForm::new(ident.clone(), FunctionKind::Nullary),
+            &[],
+            env,
+        ),
+        Node::PrefixCall { ident, rhs, .. } => call(
+            node,
+            FunctionForm::new(ident.clone(), FunctionKind::Prefix),
+            &[rhs],
+            env,
+        ),
+        Node::InfixCall {
+            ident, lhs, rhs, ..
+        } => call(
+            node,
+            FunctionForm::new(ident.clone(), FunctionKind::Infix),
+            &[lhs, rhs],
+            env,
+        ),
+        Node::PostfixCall { ident, lhs, .. } => call(
+            node,
+            FunctionForm::new(ident.clone(), FunctionKind::Postfix),
+            &[lhs],
+            env,
+        ),
+        Node::FunctionCall {
+            ident, parameters, ..
+        } => call(
+            node,
+            FunctionForm::new(ident.clone(), FunctionKind::Prefix),
+            &parameters.iter().collect::<Vec<_>>(),
+            env,
+        ),
+        _ => Err(unsupported(node)),
+    }
+}
+
+fn call(
+    node: &Node,
+    key: FunctionForm,
+    parameters: &[&Node],
+    env: &Environment,
+) -> Result<Distribution, Error> {
+    // 利用者の命令の再帰は評価と同じ上限で打ち切る
+    let _depth = env.call()?;
+
+    if let Some(builtin) = env.builtin(&key) {
+        builtin.arity().check(&key, parameters.len())?;
+
+        return builtin_distribution(node, builtin, parameters, env);
+    }
+
+    let function = env.function(&key, parameters.len())?;
+
+    if let Some((argument, scope)) = function.closure() {
+        return distribution(argument, scope);
+    }
+
+    let body = function.node().ok_or_else(|| unsupported(node))?;
+    let mut frames = vec![(HashMap::new(), 1.0)];
+
+    // 値渡しの引数は取りうる値ごとに束縛し、その確率で混ぜ合わせる
+    for (parameter, &argument) in function.parameters().iter().zip(parameters) {
+        let name = parameter.name().to_string();
+
+        if parameter.is_lazy() {
+            for (bindings, _) in &mut frames {
+                bindings.insert(
+                    name.clone(),
+                    Function::thunk(Box::new(argument.clone()), env.capture()),
+                );
+            }
+
+            continue;
+        }
+
+        if is_pool(argument, env) {
+            return Err(unsupported(node));
+        }
+
+        let values = distribution(argument, env)?;
+
+        if frames.len().saturating_mul(values.probabilities.len()) > MAX_WORK {
+            return Err(unsupported(node));
+        }
+
+        let mut bound = Vec::new();
+
+        for (bindings, p) in frames {
+            for (v, q) in values.iter() {
+                let mut bindings = bindings.clone();
+
+                bindings.insert(
+                    name.clone(),
+                    Function::new(Box::new(Node::Integer(v)), Vec::new()),
+                );
+                bound.push((bindings, p * q));
+            }
+        }
+
+        frames = bound;
+    }
+
+    let mut parts = Vec::new();
+
+    for (bindings, p) in frames {
+        parts.push((p, distribution(body, &env.call_frame(bindings))?));
+    }
+
+    Ok(Distribution::mix(parts))
+}
+
+// 引数の値の組み合わせごとに組み込みを適用する。ダイスプールを受け取れるのは集計する組み込みだけ
+fn builtin_distribution(
+    node: &Node,
+    builtin: &dyn Builtin,
+    parameters: &[&Node],
+    env: &Environment,
+) -> Result<Distribution, Error> {
+    if let (Some(kind), [parameter]) = (builtin.aggregate(), parameters) {
+        return aggregate(kind, parameter, env);
+    }
+
+    if parameters.iter().any(|p| is_pool(p, env)) {
+        return Err(unsupported(node));
+    }
+
+    let mut combinations = vec![(Vec::new(), 1.0)];
+
+    for parameter in parameters {
+        let distribution = distribution(parameter, env)?;
+
+        if combinations
+            .len()
+            .saturating_mul(distribution.probabilities.len())
+            > MAX_WORK
+        {
+            return Err(unsupported(node));
+        }
+
+        combinations = combinations
+            .into_iter()
+            .flat_map(|(values, p)| {
+                distribution.iter().map(move |(v, q)| {
+                    let mut values = values.clone();
+
+                    values.push(Object::Integer(v));
+
+                    (values, p * q)
+                })
+            })
+            .collect();
+    }
+
+    let mut parts = Vec::new();
+
+    for (values, p) in combinations {
+        let outcomes = builtin
+            .outcomes(values, env)?
+            .ok_or_else(|| unsupported(node))?;
+
+        parts.push((p, Distribution::from_pairs(outcomes)));
+    }
+
+    Ok(Distribution::mix(parts))
+}
+
+fn total(node: &Node, env: &Environment) -> Result<Distribution, Error> {
+    Ok(Distribution::mix(
+        pools(node, env)?
+            .into_iter()
+            .map(|pool| Some((pool.weight, pool.total()?)))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| unsupported(node))?,
+    ))
+}
+
+fn aggregate(kind: Aggregate, node: &Node, env: &Environment) -> Result<Distribution, Error> {
+    if !is_pool(node, env) {
+        let distribution = distribution(node, env)?;
+
+        return Ok(match kind {
+            Aggregate::Len => Distribution::point(1),
+            _ => distribution,
+        });
+    }
+
+    let mut parts = Vec::new();
+
+    for pool in pools(node, env)? {
+        parts.push((
+            pool.weight,
+            pool.aggregate(kind).ok_or_else(|| unsupported(node))?,
+        ));
+    }
+
+    Ok(Distribution::mix(parts))
+}
+
+fn pools(node: &Node, env: &Environment) -> Result<Vec<Pool>, Error> {
+    match node {
+        Node::InfixCall {
+            ident, lhs, rhs, ..
+        } if is_dice(ident, env) => {
+            let counts = distribution(lhs, env)?;
+            let faces = distribution(rhs, env)?;
+            let mut pools = Vec::new();
+
+            for (count, p) in counts.iter() {
+                for (faces, q) in faces.iter() {
+                    env.limits().check_dice(count, faces)?;
+
+                    pools.push(Pool {
+                        weight: p * q,
+                        count,
+                        faces,
+                        die: Distribution::uniform(faces),
+                        keep: None,
+                        exploded: false,
+                    });
+                }
+            }
+
+            Ok(pools)
+        }
+        Node::DiceModifier { kind, lhs, rhs, .. } => {
+            let rhs = match rhs {
+                Some(rhs) => Some(
+                    distribution(rhs, env)?
+                        .constant()
+                        .ok_or_else(|| unsupported(node))?,
+                ),
+                None => None,
+            };
+
+            pools(lhs, env)?
+                .into_iter()
+                .map(|pool| pool.modify(*kind, rhs))
+                .collect::<Option<_>>()
+                .ok_or_else(|| unsupported(node))
+        }
+        _ => Err(unsupported(node)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{eval::exec, lexer::Lexer, parser::Parser};
+
+    #[test]
+    fn distribution_test() {
+        let tests = [
+            (
+                "2D6",
+                vec![(2, 1.0 / 36.0), (7, 6.0 / 36.0), (12, 1.0 / 36.0)],
+            ),
+            ("2D6+3>=10", vec![(1, 21.0 / 36.0), (0, 15.0 / 36.0)]),
+            ("1D6*2", vec![(2, 1.0 / 6.0), (3, 0.0), (12, 1.0 / 6.0)]),
+            ("(1D2)D6", vec![(1, 1.0 / 12.0), (12, 1.0 / 72.0)]),
+            ("4D6KH3", vec![(3, 1.0 / 1296.0), (18, 21.0 / 1296.0)]),
+            ("4D6DL1", vec![(3, 1.0 / 1296.0), (18, 21.0 / 1296.0)]),
+            ("2D20KL1", vec![(1, 39.0 / 400.0), (20, 1.0 / 400.0)]),
+            ("1D6!", vec![(5, 1.0 / 6.0), (6, 0.0), (7, 1.0 / 36.0)]),
+            (
+                "1D6!p",
+                vec![(5, 1.0 / 6.0), (6, 1.0 / 36.0), (11, 1.0 / 216.0)],
+            ),
+            ("1D6!!>=5", vec![(4, 1.0 / 6.0), (5, 0.0), (6, 1.0 / 36.0)]),
+            ("1D6r1", vec![(1, 0.0), (2, 1.0 / 5.0)]),
+            ("1D6ro1", vec![(1, 1.0 / 36.0), (2, 7.0 / 36.0)]),
+            ("5B6>=5", vec![(0, 32.0 / 243.0), (5, 1.0 / 243.0)]),
+            ("max(2B6)", vec![(1, 1.0 / 36.0), (6, 11.0 / 36.0)]),
+            ("min(2B6)", vec![(1, 11.0 / 36.0), (6, 1.0 / 36.0)]),
+            ("len(4B6KH3)", vec![(3, 1.0)]),
+            ("max(1D6, 1D6)", vec![(1, 1.0 / 36.0), (6, 11.0 / 36.0)]),
+            ("rand(2, 5)", vec![(1, 0.0), (2, 1.0 / 4.0), (5, 1.0 / 4.0)]),
+            ("abs(1D6-1D6)", vec![(0, 6.0 / 36.0), (5, 2.0 / 36.0)]),
+            ("clamp(2D6, 4, 10)", vec![(4, 6.0 / 36.0), (10, 6.0 / 36.0)]),
+            ("round(1D6, 2)", vec![(1, 2.0 / 6.0), (3, 2.0 / 6.0)]),
+            ("-1D6", vec![(-6, 1.0 / 6.0), (1, 0.0)]),
+            ("1D20+-2", vec![(-1, 1.0 / 20.0), (18, 1.0 / 20.0)]),
+        ];
+
+        for (input, expected) in tests {
+            let distribution = analyze(input, &Environment::new()).unwrap();
+
+            assert!((distribution.iter().map(|(_, p)| p).sum::<f64>() - 1.0).abs() < 1e-9);
+
+            for (value, p) in expected {
+                assert!(
+                    (distribution.probability(value) - p).abs() < 1e-9,
+                    "{} {}",
+                    input,
+                    value
+                );
+            }
+        }
+
+        // 利用者の定義は組み込みのダイスや関数を隠す
+        let tests = [
+            (
+                r#"def "twice"x => x+x"#,
+                "twice(1D6)",
+                vec![(2, 1.0 / 6.0), (3, 0.0)],
+            ),
+            (
+                r#"def "twice"~x => x+x"#,
+                "twice(1D6)",
+                vec![(2, 1.0 / 36.0), (3, 2.0 / 36.0)],
+            ),
+            (r#"def n"B"m => n*m"#, "3B6>=5", vec![(1, 1.0)]),
+            (r#"def n"B"m => n*m"#, "sum(3B6)", vec![(18, 1.0)]),
+            (r#"def "sum"x => x*2"#, "sum(1D2)", vec![(2, 0.5), (4, 0.5)]),
+        ];
+
+        for (def, input, expected) in tests {
+            let mut env = Environment::new();
+            let lexer = Lexer::new(def.to_string());
+            let mut parser = Parser::new(lexer);
+
+            exec(parser.parse_statement().unwrap(), &mut env).unwrap();
+
+            let distribution = analyze(input, &env).unwrap();
+
+            for (value, p) in expected {
+                assert!(
+                    (distribution.probability(value) - p).abs() < 1e-9,
+                    "{} {}",
+                    input,
+                    value
+                );
+            }
+        }
+
+        // 登録から外した組み込みは分布でも使えない
+        let mut env = Environment::new();
+
+        for name in ["rand", "max"] {
+            let key = FunctionForm::new(name.to_string(), FunctionKind::Prefix);
+
+            env.unregister(&key);
+
+            assert_eq!(
+                analyze(&format!("{}(1, 2)", name), &env)
+                    .unwrap_err()
+                    .kind(),
+                &ErrorKind::UnknownCommand(key)
+            );
+        }
+
+        let distribution = analyze("4D6KH3", &Environment::new()).unwrap();
+
+        assert!((distribution.mean() - 15869.0 / 1296.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn unsupported_distribution_test() {
+        let inputs = [
+            "1D6/0",
+            "2B6",
+            "3D6!KH1",
+            "4D6KH3KH2",
+            "1D6KH(1D2)",
+            "A",
+            "count(3B6, 6)",
+            "-(2B6)",
+            "1D6!>=1",
+            "1D6!!<>7",
+        ];
+
+        for input in inputs {
+            assert!(analyze(input, &Environment::new()).is_err());
+        }
+
+        // 組み合わせが多すぎる式は試行に任せる
+        let inputs = [
+            "2D1000000",
+            "200D1000",
+            "1000D1000KH500",
+            "1D1000+1D10000",
+            "1D1000!>=2",
+            "rand(1, 1000000000000)",
+            "(1D1000)R(1D10000)",
+            "max(1D1000, 1D1000, 1D1000)",
+        ];
+        let mut env = Environment::new();
+        let lexer = Lexer::new(r#"def n"R"m => n+m"#.to_string());
+
+        exec(Parser::new(lexer).parse_statement().unwrap(), &mut env).unwrap();
+
+        for input in inputs {
+            assert!(
+                matches!(
+                    analyze(input, &env).unwrap_err().kind(),
+                    ErrorKind::Unsupported(_)
+                ),
+                "{}",
+                input
+            );
+        }
+
+        // 最後の桁の後で分布を二乗しない
+        let distribution = analyze("1D2000", &Environment::new()).unwrap();
+
+        assert_eq!(distribution.probabilities().len(), 2000);
+    }
+}
@@ -1,35 +1,213 @@
-use crate::{ast::Node, builtin::eval_builtin, eval::eval, object::Object};
-use std::collections::HashMap;
+use crate::{
+    ast::{CommandKind, Node, Parameter},
+    builtin::{standard, Builtin},
+    error::{Error, ErrorKind},
+    eval::eval,
+    limits::{Guard, Limits, Usage},
+    object::Evaluation,
+    random::{RandomSource, SeededRandom},
+    IntegerType,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
-#[derive(Clone)]
+// 呼び出しは引数の束縛、同じ形の利用者の定義、組み込みの順に探す。定義を消せば隠れていた組み込みが再び見える。
+// 大域の定義と組み込みは複製した環境どうしで共有し、書き換えるときにだけ複製する
 pub struct Environment {
-    store: HashMap<FunctionForm, Function>,
+    store: Arc<HashMap<FunctionForm, Function>>,
+    frame: Option<Arc<Frame>>,
+    builtins: Arc<HashMap<FunctionForm, Arc<dyn Builtin>>>,
+    random: Arc<Mutex<Box<dyn RandomSource>>>,
+    limits: Limits,
+    usage: Arc<Usage>,
+}
+// 複製した環境は数え始めの手数や深さを共有しない。別のスレッドで評価しても互いに干渉しない
+impl Clone for Environment {
+    fn clone(&self) -> Self {
+        Self {
+            usage: Arc::new(Usage::default()),
+            ..self.capture()
+        }
+    }
 }
 impl Environment {
     pub fn new() -> Self {
+        Self::with_random(SeededRandom::from_entropy())
+    }
+
+    pub fn with_random(random: impl RandomSource + 'static) -> Self {
         Self {
-            store: HashMap::new(),
+            store: Arc::new(HashMap::new()),
+            frame: None,
+            builtins: Arc::new(standard().into_iter().map(|b| (b.form(), b)).collect()),
+            random: Arc::new(Mutex::new(Box::new(random))),
+            limits: Limits::default(),
+            usage: Arc::new(Usage::default()),
         }
     }
 
+    // 関数の定義はそのままに、乱数源と評価の資源の数え方を共有しない複製を作る
+    pub fn fork(&self, random: impl RandomSource + 'static) -> Self {
+        Self {
+            random: Arc::new(Mutex::new(Box::new(random))),
+            ..self.clone()
+        }
+    }
+
+    // 複製した環境とも共有している乱数源を差し替える
+    pub fn set_random(&mut self, random: impl RandomSource + 'static) {
+        *self.random.lock().unwrap() = Box::new(random);
+    }
+
+    // 試行で近似するときの seed。固定した乱数源から取り出せば同じ結果を再現できる
+    pub fn seed(&self) -> u64 {
+        (self.random.lock().unwrap().roll(1 << 64) - 1) as u64
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // 式を一つ評価するたびに呼び、手数を確かめる
+    pub(crate) fn enter(&self) -> Result<Guard<'_>, Error> {
+        self.usage.enter(&self.limits)
+    }
+
+    // 組み込みか定義された命令を呼び出すたびに呼び、再帰の深さを確かめる
+    pub(crate) fn call(&self) -> Result<Guard<'_>, Error> {
+        self.usage.call(&self.limits)
+    }
+
+    pub fn roll(&self, faces: IntegerType) -> Result<IntegerType, Error> {
+        self.usage.roll(&self.limits)?;
+
+        Ok(self.random.lock().unwrap().roll(faces))
+    }
+
     pub fn get_and_eval(
         &self,
         key: &FunctionForm,
-        parameters: Vec<Box<Node>>,
-    ) -> Result<Object, ()> {
-        if let Some(o) = eval_builtin(key, parameters.clone(), self) {
-            return Ok(o);
+        parameters: &[&Node],
+    ) -> Result<Evaluation, Error> {
+        let _depth = self.call()?;
+
+        if let Some(builtin) = self.builtin(key) {
+            builtin.arity().check(key, parameters.len())?;
+
+            let args = parameters
+                .iter()
+                .map(|p| eval(p, self))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            return builtin.eval_traced(args, self);
         }
 
-        self.store.get(key).ok_or(())?.eval(parameters, self)
+        self.function(key, parameters.len())?.eval(parameters, self)
+    }
+
+    // 定義された関数を引数の個数を確かめた上で取り出す
+    pub(crate) fn function(&self, key: &FunctionForm, arity: usize) -> Result<&Function, Error> {
+        let function = self
+            .lookup(key)
+            .ok_or_else(|| Error::new(ErrorKind::UnknownCommand(key.clone())))?;
+
+        if function.parameters.len() != arity {
+            return Err(Error::new(ErrorKind::ArityMismatch {
+                form: key.clone(),
+                expected: function.parameters.len(),
+                found: arity,
+            }));
+        }
+
+        Ok(function)
+    }
+
+    // 引数の束縛は無引数の命令として、呼び出しの中でだけ大域の定義を隠す
+    fn lookup(&self, key: &FunctionForm) -> Option<&Function> {
+        let bound = match (&self.frame, key.kind()) {
+            (Some(frame), FunctionKind::Nullary) => frame.bindings.get(key.name()),
+            _ => None,
+        };
+
+        bound.or_else(|| self.store.get(key))
+    }
+
+    // 評価の途中で引数を持ち出す環境。手数や深さは呼び出し元と共有する
+    pub(crate) fn capture(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            frame: self.frame.clone(),
+            builtins: self.builtins.clone(),
+            random: self.random.clone(),
+            limits: self.limits,
+            usage: self.usage.clone(),
+        }
+    }
+
+    // 定義の本体を評価する環境。定義は大域にしか書けないので、呼び出し元の束縛は見えない
+    pub(crate) fn call_frame(&self, bindings: HashMap<String, Function>) -> Self {
+        Self {
+            frame: Some(Arc::new(Frame { bindings })),
+            ..self.capture()
+        }
     }
 
     pub fn insert(&mut self, key: FunctionForm, value: Function) -> Option<Function> {
-        self.store.insert(key, value)
+        Arc::make_mut(&mut self.store).insert(key, value)
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = (&FunctionForm, &Function)> {
+        self.store.iter()
+    }
+
+    // 同じ形の組み込みが既にあれば置き換えて、前の組み込みを返す
+    pub fn register(&mut self, builtin: impl Builtin + 'static) -> Option<Arc<dyn Builtin>> {
+        let builtin: Arc<dyn Builtin> = Arc::new(builtin);
+
+        Arc::make_mut(&mut self.builtins).insert(builtin.form(), builtin)
+    }
+
+    pub fn unregister(&mut self, key: &FunctionForm) -> Option<Arc<dyn Builtin>> {
+        Arc::make_mut(&mut self.builtins).remove(key)
+    }
+
+    // 利用者の定義に隠されていない組み込み
+    pub fn builtin(&self, key: &FunctionForm) -> Option<&dyn Builtin> {
+        if self.lookup(key).is_some() {
+            return None;
+        }
+
+        self.builtins.get(key).map(|b| b.as_ref())
+    }
+
+    pub fn is_builtin(&self, key: &FunctionForm) -> bool {
+        self.builtin(key).is_some()
+    }
+
+    // 利用者の定義に隠されているものも含めた、登録されている全ての組み込み
+    pub fn builtins(&self) -> impl Iterator<Item = &dyn Builtin> {
+        self.builtins.values().map(|b| b.as_ref())
+    }
+}
+
+// 一回の呼び出しで束縛した引数
+struct Frame {
+    bindings: HashMap<String, Function>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum FunctionKind {
     Nullary,
     Prefix,
@@ -37,7 +215,33 @@ pub enum FunctionKind {
     Postfix,
 }
 
-#[derive(Clone, Hash, PartialEq, Eq)]
+impl From<CommandKind> for FunctionKind {
+    fn from(kind: CommandKind) -> Self {
+        match kind {
+            CommandKind::Nullary => FunctionKind::Nullary,
+            CommandKind::Prefix => FunctionKind::Prefix,
+            CommandKind::Infix => FunctionKind::Infix,
+            CommandKind::Postfix => FunctionKind::Postfix,
+        }
+    }
+}
+
+impl std::fmt::Display for FunctionKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                FunctionKind::Nullary => "nullary",
+                FunctionKind::Prefix => "prefix",
+                FunctionKind::Infix => "infix",
+                FunctionKind::Postfix => "postfix",
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct FunctionForm {
     name: String,
     kind: FunctionKind,
@@ -53,32 +257,142 @@ impl FunctionForm {
     pub fn kind(&self) -> &FunctionKind {
         &self.kind
     }
+
+    // 定義の左辺と同じ書き方 n "D" m。名前と引数の境目が分かるよう空白を挟む
+    pub fn pattern(&self, parameters: &[Parameter]) -> String {
+        let quoted = format!("\"{}\"", self.name);
+
+        match (&self.kind, parameters) {
+            (FunctionKind::Nullary, _) => self.name.clone(),
+            (FunctionKind::Prefix, [rhs]) => format!("{} {}", quoted, rhs),
+            (FunctionKind::Infix, [lhs, rhs]) => format!("{} {} {}", lhs, quoted, rhs),
+            (FunctionKind::Postfix, [lhs]) => format!("{} {}", lhs, quoted),
+            _ => format!(
+                "{}({})",
+                self.name,
+                parameters
+                    .iter()
+                    .map(|p| p.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for FunctionForm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} command '{}'", self.kind, self.name)
+    }
+}
+
+// 命令の本体。値渡しで束縛した引数は評価した結果を、lazy な引数は式と呼び出し元の環境を持つ
+enum Body {
+    Node(Box<Node>),
+    Thunk(Box<Node>, Environment),
+    Value(Evaluation),
+}
+// 持ち出した引数は複製しても呼び出し元と手数や深さを共有する
+impl Clone for Body {
+    fn clone(&self) -> Self {
+        match self {
+            Body::Node(node) => Body::Node(node.clone()),
+            Body::Thunk(node, env) => Body::Thunk(node.clone(), env.capture()),
+            Body::Value(evaluation) => Body::Value(evaluation.clone()),
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct Function {
-    node: Box<Node>,
-    parameters: Vec<String>,
+    body: Body,
+    parameters: Vec<Parameter>,
 }
 impl Function {
-    pub fn new(node: Box<Node>, parameters: Vec<String>) -> Self {
-        Self { node, parameters }
+    pub fn new(node: Box<Node>, parameters: Vec<Parameter>) -> Self {
+        Self {
+            body: Body::Node(node),
+            parameters,
+        }
+    }
+
+    // 評価済みの値を返す無引数の関数
+    pub fn value(evaluation: Evaluation) -> Self {
+        Self {
+            body: Body::Value(evaluation),
+            parameters: Vec::new(),
+        }
     }
 
-    pub fn eval(&self, parameters: Vec<Box<Node>>, env: &Environment) -> Result<Object, ()> {
-        if parameters.len() != self.parameters.len() {
-            return Err(());
+    // 参照するたびに呼び出し元の環境で評価し直す引数
+    pub(crate) fn thunk(node: Box<Node>, env: Environment) -> Self {
+        Self {
+            body: Body::Thunk(node, env),
+            parameters: Vec::new(),
         }
+    }
 
-        let mut env = env.clone();
+    pub fn node(&self) -> Option<&Node> {
+        match &self.body {
+            Body::Node(node) => Some(node),
+            Body::Thunk(..) | Body::Value(_) => None,
+        }
+    }
 
-        for (i, param) in parameters.into_iter().enumerate() {
-            env.insert(
-                FunctionForm::new(self.parameters[i].to_string(), FunctionKind::Nullary),
-                Function::new(param, Vec::new()),
-            );
+    pub(crate) fn closure(&self) -> Option<(&Node, &Environment)> {
+        match &self.body {
+            Body::Thunk(node, env) => Some((node, env)),
+            Body::Node(_) | Body::Value(_) => None,
         }
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
 
-        eval(self.node.clone(), &env)
+    // 引数の個数は呼び出し側で確かめる
+    pub fn eval(&self, parameters: &[&Node], env: &Environment) -> Result<Evaluation, Error> {
+        let node = match &self.body {
+            Body::Node(node) => node,
+            Body::Thunk(node, scope) => return eval(node, scope),
+            Body::Value(evaluation) => return Ok(evaluation.clone()),
+        };
+        let mut bindings = HashMap::new();
+        let mut dice = Vec::new();
+
+        // 値渡しの引数は呼び出し元で一度だけ評価し、振ったダイスは呼び出しの結果に含める。
+        // 参照した箇所には値だけを表示し、何度参照しても振ったのは一度だけだと分かるようにする。
+        // lazy な引数は式のまま束縛し、参照するたびに評価し直す
+        for (parameter, &param) in self.parameters.iter().zip(parameters) {
+            let function = if parameter.is_lazy() {
+                Function::thunk(Box::new(param.clone()), env.capture())
+            } else {
+                let evaluation = eval(param, env)?;
+
+                dice.extend_from_slice(evaluation.dice());
+
+                let object = evaluation.into_object();
+
+                Function::value(Evaluation::new(object.clone(), object.to_string()))
+            };
+
+            bindings.insert(parameter.name().to_string(), function);
+        }
+
+        // 本体の位置は呼び出し元の入力とは関係がないので捨てる
+        let evaluation = eval(node, &env.call_frame(bindings)).map_err(Error::without_span)?;
+
+        dice.extend_from_slice(evaluation.dice());
+
+        Ok(evaluation.with_dice(dice))
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.body {
+            Body::Node(node) | Body::Thunk(node, _) => write!(f, "{}", node),
+            Body::Value(evaluation) => write!(f, "{}", evaluation.trace()),
+        }
     }
 }
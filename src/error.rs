@@ -0,0 +1,175 @@
+use crate::{environment::FunctionForm, token::Span, IntegerType};
+use dyce_lang::error::{ParseError, ParseErrorKind};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    kind: ErrorKind,
+    span: Option<Span>,
+}
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    // より内側で位置が決まっていればそちらを優先する
+    pub fn with_span(self, span: Span) -> Self {
+        Self {
+            span: self.span.or(Some(span)),
+            ..self
+        }
+    }
+
+    pub fn without_span(self) -> Self {
+        Self { span: None, ..self }
+    }
+
+    // 入力の該当する行を示し、その位置に ^ を付けた表示
+    pub fn diagnostic(&self, input: &str) -> String {
+        let span = match self.span {
+            Some(span) => span,
+            None => return format!("error: {}", self.kind),
+        };
+        let before = input.chars().take(span.start()).collect::<String>();
+        let row = before.matches('\n').count();
+        let column = before.chars().rev().take_while(|&c| c != '\n').count();
+        let line = input.split('\n').nth(row).unwrap_or("").trim_end();
+        let width = (span.end() - span.start())
+            .min(line.chars().count().saturating_sub(column))
+            .max(1);
+        let location = if input.trim_end().contains('\n') {
+            format!("line {}: ", row + 1)
+        } else {
+            String::new()
+        };
+
+        format!(
+            "{}\n{}{}\n{}error: {}",
+            line,
+            " ".repeat(column),
+            "^".repeat(width),
+            location,
+            self.kind
+        )
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Self {
+        Self::new(ErrorKind::Parse(e.kind().clone())).with_span(e.span())
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    // 字句解析と構文解析
+    Parse(ParseErrorKind),
+    // 評価
+    UnknownCommand(FunctionForm),
+    ArityMismatch {
+        form: FunctionForm,
+        expected: usize,
+        found: usize,
+    },
+    DivisionByZero,
+    TypeMismatch {
+        expected: String,
+        found: String,
+    },
+    Overflow,
+    InvalidDice {
+        count: IntegerType,
+        faces: IntegerType,
+    },
+    InvalidArgument(String),
+    // 資源の上限
+    RecursionLimit(usize),
+    StepLimit(usize),
+    RollLimit(usize),
+    TooManyDice {
+        count: IntegerType,
+        max: IntegerType,
+    },
+    TooManyFaces {
+        faces: IntegerType,
+        max: IntegerType,
+    },
+    // 確率分布を厳密には求められない式
+    Unsupported(String),
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::Parse(kind) => write!(f, "{}", kind),
+            ErrorKind::UnknownCommand(form) => write!(f, "unknown {}", form),
+            ErrorKind::ArityMismatch {
+                form,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} takes {} argument(s) but {} were given",
+                form, expected, found
+            ),
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "type mismatch: expected {}, found {}", expected, found)
+            }
+            ErrorKind::Overflow => write!(f, "integer overflow"),
+            ErrorKind::InvalidDice { count, faces } => write!(f, "cannot roll {}D{}", count, faces),
+            ErrorKind::InvalidArgument(message) => write!(f, "invalid argument: {}", message),
+            ErrorKind::RecursionLimit(max) => {
+                write!(
+                    f,
+                    "recursion limit exceeded: expressions and commands nested deeper than {}",
+                    max
+                )
+            }
+            ErrorKind::StepLimit(max) => {
+                write!(
+                    f,
+                    "step limit exceeded: evaluation took more than {} steps",
+                    max
+                )
+            }
+            ErrorKind::RollLimit(max) => {
+                write!(f, "roll limit exceeded: more than {} dice rolled", max)
+            }
+            ErrorKind::TooManyDice { count, max } => {
+                write!(f, "cannot roll {} dice at once (at most {})", count, max)
+            }
+            ErrorKind::TooManyFaces { faces, max } => {
+                write!(
+                    f,
+                    "cannot roll a die with {} faces (at most {})",
+                    faces, max
+                )
+            }
+            ErrorKind::Unsupported(expr) => {
+                write!(f, "cannot compute the exact distribution of {}", expr)
+            }
+        }
+    }
+}
@@ -1,100 +1,280 @@
 use crate::{
-    ast::{BinaryExprKind, ComparisonExprKind, Node},
-    environment::{Environment, FunctionForm, FunctionKind},
-    object::Object,
+    ast::{
+        BinaryExprKind, ComparisonExprKind, Node, Statement, COMPARISON_PRECEDENCE,
+        UNARY_PRECEDENCE,
+    },
+    builtin::eval_dice,
+    environment::{Environment, Function, FunctionForm, FunctionKind},
+    error::{Error, ErrorKind},
+    object::{Evaluation, Object},
+    IntegerType,
 };
 
-pub fn eval(node: Box<Node>, env: &Environment) -> Result<Object, ()> {
-    if let Node::Integer(val) = *node {
-        return Ok(Object::Integer(val));
-    }
+// 失敗した場合は、より内側で位置が決まっていなければこの式の位置を付ける
+pub fn eval(node: &Node, env: &Environment) -> Result<Evaluation, Error> {
+    let span = node.span();
 
-    if let Node::BinaryExpr { kind, lhs, rhs } = *node {
-        let lhs = eval(lhs, env)?;
-        let rhs = eval(rhs, env)?;
-
-        if let Object::Integer(lhs) = lhs {
-            if let Object::Integer(rhs) = rhs {
-                return Ok(Object::Integer(match kind {
-                    BinaryExprKind::Add => lhs + rhs,
-                    BinaryExprKind::Sub => lhs - rhs,
-                    BinaryExprKind::Mul => lhs * rhs,
-                    BinaryExprKind::Div => {
-                        if rhs == 0 {
-                            return Err(());
-                        } else {
-                            lhs / rhs
-                        }
-                    }
-                }));
-            }
+    env.enter()
+        .and_then(|_nesting| eval_node(node, env))
+        .map_err(|e| match span {
+            Some(span) => e.with_span(span),
+            None => e,
+        })
+}
+
+// let は式をその場で振って値を束縛し、def は式を評価せずに命令として登録する
+pub fn exec(statement: Statement, env: &mut Environment) -> Result<Option<Evaluation>, Error> {
+    match statement {
+        Statement::Let { name, expr } => {
+            let span = expr.span();
+            let evaluation = eval(&expr, env)?;
+            let value = match evaluation.clone().into_object() {
+                Object::Integer(v) => v,
+                object => {
+                    let error = Error::new(ErrorKind::TypeMismatch {
+                        expected: "integer".to_string(),
+                        found: object.type_name().to_string(),
+                    });
+
+                    return Err(match span {
+                        Some(span) => error.with_span(span),
+                        None => error,
+                    });
+                }
+            };
+
+            env.insert(
+                FunctionForm::new(name, FunctionKind::Nullary),
+                Function::new(Box::new(Node::Integer(value)), Vec::new()),
+            );
+
+            Ok(Some(evaluation))
         }
+        Statement::Def(def) => {
+            // 同じ形の組み込みは隠されるだけで、登録は残る
+            env.insert(
+                FunctionForm::new(def.name().to_string(), def.kind().into()),
+                Function::new(Box::new(def.expr().clone()), def.parameters().to_vec()),
+            );
 
-        return Err(());
+            Ok(None)
+        }
+        Statement::Expr(expr) => Ok(Some(eval(&expr, env)?)),
     }
+}
 
-    if let Node::ComparisonExpr { kind, lhs, rhs } = *node {
-        let lhs = eval(lhs, env)?;
-        let rhs = eval(rhs, env)?;
+fn eval_node(node: &Node, env: &Environment) -> Result<Evaluation, Error> {
+    match node {
+        &Node::Integer(val) => Ok(Evaluation::new(Object::Integer(val), val.to_string())),
+        &Node::BinaryExpr {
+            kind,
+            ref lhs,
+            ref rhs,
+            ..
+        } => {
+            let lhs = eval(lhs, env)?;
+            let rhs = eval(rhs, env)?;
+            let dice = [lhs.dice(), rhs.dice()].concat();
+            let precedence = kind.precedence();
+            let trace = format!(
+                "{}{}{}",
+                lhs.operand_trace(precedence, false),
+                kind,
+                rhs.operand_trace(precedence, true)
+            );
 
-        if kind == ComparisonExprKind::Eq {
-            return Ok(Object::Boolean(lhs == rhs));
+            Ok(
+                Evaluation::new(binary(kind, lhs.into_object(), rhs.into_object())?, trace)
+                    .with_precedence(precedence)
+                    .with_dice(dice),
+            )
         }
+        &Node::ComparisonExpr {
+            kind,
+            ref lhs,
+            ref rhs,
+            ..
+        } => {
+            let lhs = eval(lhs, env)?;
+            let rhs = eval(rhs, env)?;
+            let dice = [lhs.dice(), rhs.dice()].concat();
+            let trace = format!(
+                "{}{}{}",
+                lhs.operand_trace(COMPARISON_PRECEDENCE, false),
+                kind,
+                rhs.operand_trace(COMPARISON_PRECEDENCE, true)
+            );
 
-        if kind == ComparisonExprKind::Ne {
-            return Ok(Object::Boolean(lhs != rhs));
+            Ok(Evaluation::new(
+                comparison(kind, lhs.into_object(), rhs.into_object())?,
+                trace,
+            )
+            .with_precedence(COMPARISON_PRECEDENCE)
+            .with_dice(dice))
         }
+        Node::Negation { operand, .. } => {
+            let operand = eval(operand, env)?;
+            let dice = operand.dice().to_vec();
+            let trace = format!("-{}", operand.operand_trace(UNARY_PRECEDENCE, true));
 
-        if let Object::Integer(lhs) = lhs {
-            if let Object::Integer(rhs) = rhs {
-                return Ok(Object::Boolean(match kind {
-                    ComparisonExprKind::Lt => lhs < rhs,
-                    ComparisonExprKind::Le => lhs <= rhs,
-                    ComparisonExprKind::Gt => lhs > rhs,
-                    ComparisonExprKind::Ge => lhs >= rhs,
-                    _ => return Err(()),
-                }));
-            }
+            Ok(Evaluation::new(negate(operand.into_object())?, trace)
+                .with_precedence(UNARY_PRECEDENCE)
+                .with_dice(dice))
+        }
+        Node::NullaryCall { ident, .. } => env.get_and_eval(
+            &FunctionForm::new(ident.clone(), FunctionKind::Nullary),
+            &[],
+        ),
+        Node::PrefixCall { ident, rhs, .. } => env.get_and_eval(
+            &FunctionForm::new(ident.clone(), FunctionKind::Prefix),
+            &[rhs],
+        ),
+        Node::InfixCall {
+            ident, lhs, rhs, ..
+        } => env.get_and_eval(
+            &FunctionForm::new(ident.clone(), FunctionKind::Infix),
+            &[lhs, rhs],
+        ),
+        Node::PostfixCall { ident, lhs, .. } => env.get_and_eval(
+            &FunctionForm::new(ident.clone(), FunctionKind::Postfix),
+            &[lhs],
+        ),
+        Node::DiceModifier { .. } => eval_dice(node, env),
+        // 引数の個数が一つでない関数呼び出しも前置の命令として探す
+        Node::FunctionCall {
+            ident, parameters, ..
+        } => env.get_and_eval(
+            &FunctionForm::new(ident.clone(), FunctionKind::Prefix),
+            &parameters.iter().collect::<Vec<_>>(),
+        ),
+    }
+}
+
+fn binary(kind: BinaryExprKind, lhs: Object, rhs: Object) -> Result<Object, Error> {
+    match (lhs, rhs) {
+        (Object::Integer(lhs), Object::Integer(rhs)) => {
+            Ok(Object::Integer(arithmetic(kind, lhs, rhs)?))
         }
+        // ダイスプール同士の加算はプールの結合
+        (Object::List(mut lhs), Object::List(rhs)) if kind == BinaryExprKind::Add => {
+            lhs.extend(rhs);
 
-        return Err(());
+            Ok(Object::List(lhs))
+        }
+        (Object::List(lhs), Object::Integer(rhs)) => Ok(Object::List(
+            lhs.into_iter()
+                .map(|v| arithmetic(kind, v, rhs))
+                .collect::<Result<_, _>>()?,
+        )),
+        (Object::Integer(lhs), Object::List(rhs)) => Ok(Object::List(
+            rhs.into_iter()
+                .map(|v| arithmetic(kind, lhs, v))
+                .collect::<Result<_, _>>()?,
+        )),
+        (lhs, rhs) => Err(type_mismatch(lhs, rhs)),
     }
+}
 
-    if let Node::NullaryCall(name) = *node {
-        return env.get_and_eval(
-            &FunctionForm::new(name.clone(), FunctionKind::Nullary),
-            Vec::new(),
-        );
+// ダイスプールは出目をそれぞれ符号反転する
+fn negate(operand: Object) -> Result<Object, Error> {
+    let negate = |v: IntegerType| {
+        v.checked_neg()
+            .ok_or_else(|| Error::new(ErrorKind::Overflow))
+    };
+
+    match operand {
+        Object::Integer(v) => Ok(Object::Integer(negate(v)?)),
+        Object::List(list) => Ok(Object::List(
+            list.into_iter().map(negate).collect::<Result<_, _>>()?,
+        )),
+        object => Err(Error::new(ErrorKind::TypeMismatch {
+            expected: "integer".to_string(),
+            found: object.type_name().to_string(),
+        })),
     }
+}
 
-    if let Node::PrefixCall { ident, rhs } = *node {
-        return env.get_and_eval(
-            &FunctionForm::new(ident.clone(), FunctionKind::Prefix),
-            vec![rhs],
-        );
+fn comparison(kind: ComparisonExprKind, lhs: Object, rhs: Object) -> Result<Object, Error> {
+    // ダイスプールとの比較は条件を満たすダイスの個数を返す
+    if let Object::List(dice) = &lhs {
+        if let Object::Integer(rhs) = rhs {
+            return Ok(Object::Integer(
+                dice.iter().filter(|&&v| compare(kind, v, rhs)).count() as IntegerType,
+            ));
+        }
     }
 
-    if let Node::InfixCall { ident, lhs, rhs } = *node {
-        return env.get_and_eval(
-            &FunctionForm::new(ident.clone(), FunctionKind::Infix),
-            vec![lhs, rhs],
-        );
+    if kind == ComparisonExprKind::Eq {
+        return Ok(Object::Boolean(lhs == rhs));
     }
 
-    if let Node::PostfixCall { ident, lhs } = *node {
-        return env.get_and_eval(
-            &FunctionForm::new(ident.clone(), FunctionKind::Postfix),
-            vec![lhs],
-        );
+    if kind == ComparisonExprKind::Ne {
+        return Ok(Object::Boolean(lhs != rhs));
     }
 
-    Err(())
+    match (lhs, rhs) {
+        (Object::Integer(lhs), Object::Integer(rhs)) => {
+            Ok(Object::Boolean(compare(kind, lhs, rhs)))
+        }
+        (lhs, rhs) => Err(type_mismatch(lhs, rhs)),
+    }
+}
+
+// 整数を期待する演算に整数以外の値が渡された
+fn type_mismatch(lhs: Object, rhs: Object) -> Error {
+    let found = match lhs {
+        Object::Integer(_) => rhs,
+        _ => lhs,
+    };
+
+    Error::new(ErrorKind::TypeMismatch {
+        expected: "integer".to_string(),
+        found: found.type_name().to_string(),
+    })
+}
+
+pub fn arithmetic(
+    kind: BinaryExprKind,
+    lhs: IntegerType,
+    rhs: IntegerType,
+) -> Result<IntegerType, Error> {
+    let result = match kind {
+        BinaryExprKind::Add => lhs.checked_add(rhs),
+        BinaryExprKind::Sub => lhs.checked_sub(rhs),
+        BinaryExprKind::Mul => lhs.checked_mul(rhs),
+        BinaryExprKind::Div => {
+            if rhs == 0 {
+                return Err(Error::new(ErrorKind::DivisionByZero));
+            }
+
+            lhs.checked_div(rhs)
+        }
+    };
+
+    result.ok_or_else(|| Error::new(ErrorKind::Overflow))
+}
+
+pub fn compare(kind: ComparisonExprKind, lhs: IntegerType, rhs: IntegerType) -> bool {
+    match kind {
+        ComparisonExprKind::Eq => lhs == rhs,
+        ComparisonExprKind::Ne => lhs != rhs,
+        ComparisonExprKind::Lt => lhs < rhs,
+        ComparisonExprKind::Le => lhs <= rhs,
+        ComparisonExprKind::Gt => lhs > rhs,
+        ComparisonExprKind::Ge => lhs >= rhs,
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{environment::Function, lexer::Lexer, parser::Parser, IntegerType};
+    use crate::{ast::Parameter, token::Span};
+    use crate::{
+        environment::Function,
+        lexer::Lexer,
+        parser::Parser,
+        random::{ScriptedRandom, SeededRandom},
+    };
 
     #[test]
     fn nullary_call_eval_test() {
@@ -113,7 +293,10 @@ mod test {
             let mut parser = Parser::new(lexer);
             let node = parser.parse().unwrap();
 
-            assert_eq!(eval(node, &env).unwrap(), Object::Integer(expected));
+            assert_eq!(
+                eval(&node, &env).unwrap().into_object(),
+                Object::Integer(expected)
+            );
         }
     }
 
@@ -127,10 +310,14 @@ mod test {
             Function::new(
                 Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Add,
-                    lhs: Box::new(Node::NullaryCall("x".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "x".to_string(),
+                        span: Span::default(),
+                    }),
                     rhs: Box::new(Node::Integer(1)),
+                    span: Span::default(),
                 }),
-                vec!["x".to_string()],
+                vec![Parameter::new("x".to_string())],
             ),
         );
         env.insert(
@@ -138,10 +325,14 @@ mod test {
             Function::new(
                 Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Sub,
-                    lhs: Box::new(Node::NullaryCall("x".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "x".to_string(),
+                        span: Span::default(),
+                    }),
                     rhs: Box::new(Node::Integer(1)),
+                    span: Span::default(),
                 }),
-                vec!["x".to_string()],
+                vec![Parameter::new("x".to_string())],
             ),
         );
 
@@ -150,7 +341,10 @@ mod test {
             let mut parser = Parser::new(lexer);
             let node = parser.parse().unwrap();
 
-            assert_eq!(eval(node, &env).unwrap(), Object::Integer(expected));
+            assert_eq!(
+                eval(&node, &env).unwrap().into_object(),
+                Object::Integer(expected)
+            );
         }
     }
 
@@ -164,10 +358,20 @@ mod test {
             Function::new(
                 Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Add,
-                    lhs: Box::new(Node::NullaryCall("x".to_string())),
-                    rhs: Box::new(Node::NullaryCall("y".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "x".to_string(),
+                        span: Span::default(),
+                    }),
+                    rhs: Box::new(Node::NullaryCall {
+                        ident: "y".to_string(),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
                 }),
-                vec!["x".to_string(), "y".to_string()],
+                vec![
+                    Parameter::new("x".to_string()),
+                    Parameter::new("y".to_string()),
+                ],
             ),
         );
         env.insert(
@@ -175,10 +379,20 @@ mod test {
             Function::new(
                 Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Sub,
-                    lhs: Box::new(Node::NullaryCall("x".to_string())),
-                    rhs: Box::new(Node::NullaryCall("y".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "x".to_string(),
+                        span: Span::default(),
+                    }),
+                    rhs: Box::new(Node::NullaryCall {
+                        ident: "y".to_string(),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
                 }),
-                vec!["x".to_string(), "y".to_string()],
+                vec![
+                    Parameter::new("x".to_string()),
+                    Parameter::new("y".to_string()),
+                ],
             ),
         );
 
@@ -187,7 +401,10 @@ mod test {
             let mut parser = Parser::new(lexer);
             let node = parser.parse().unwrap();
 
-            assert_eq!(eval(node, &env).unwrap(), Object::Integer(expected));
+            assert_eq!(
+                eval(&node, &env).unwrap().into_object(),
+                Object::Integer(expected)
+            );
         }
     }
 
@@ -201,10 +418,14 @@ mod test {
             Function::new(
                 Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Mul,
-                    lhs: Box::new(Node::NullaryCall("n".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "n".to_string(),
+                        span: Span::default(),
+                    }),
                     rhs: Box::new(Node::Integer(2)),
+                    span: Span::default(),
                 }),
-                vec!["n".to_string()],
+                vec![Parameter::new("n".to_string())],
             ),
         );
         env.insert(
@@ -212,10 +433,14 @@ mod test {
             Function::new(
                 Box::new(Node::BinaryExpr {
                     kind: BinaryExprKind::Mul,
-                    lhs: Box::new(Node::NullaryCall("n".to_string())),
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "n".to_string(),
+                        span: Span::default(),
+                    }),
                     rhs: Box::new(Node::Integer(3)),
+                    span: Span::default(),
                 }),
-                vec!["n".to_string()],
+                vec![Parameter::new("n".to_string())],
             ),
         );
 
@@ -224,7 +449,10 @@ mod test {
             let mut parser = Parser::new(lexer);
             let node = parser.parse().unwrap();
 
-            assert_eq!(eval(node, &env).unwrap(), Object::Integer(expected));
+            assert_eq!(
+                eval(&node, &env).unwrap().into_object(),
+                Object::Integer(expected)
+            );
         }
     }
 
@@ -238,7 +466,7 @@ mod test {
             let node = parser.parse().unwrap();
 
             assert_eq!(
-                eval(node, &Environment::new()).unwrap(),
+                eval(&node, &Environment::new()).unwrap().into_object(),
                 Object::Integer(input.parse::<IntegerType>().unwrap())
             );
         }
@@ -251,6 +479,15 @@ mod test {
             ("1+(2-3)*4-6/2", -6),
             ("1+(2-3)*(4-6)/2", 2),
             ("1+((2-3)*4-6)/2", -4),
+            ("-3", -3),
+            ("+3", 3),
+            ("2--3", 5),
+            ("-2*3", -6),
+            ("1D1+-2", -1),
+            ("1D1*-1", -1),
+            ("-2D1", -2),
+            ("-(2D1+1)", -3),
+            ("abs(-5)", 5),
         ];
 
         for (input, expected) in tests {
@@ -259,12 +496,321 @@ mod test {
             let node = parser.parse().unwrap();
 
             assert_eq!(
-                eval(node, &Environment::new()).unwrap(),
+                eval(&node, &Environment::new()).unwrap().into_object(),
                 Object::Integer(expected)
             );
         }
     }
 
+    #[test]
+    fn dice_eval_test() {
+        let tests = [
+            ("4D1", 4),
+            ("4D1KH3", 3),
+            ("2d1kl", 1),
+            ("5D1DL2", 3),
+            ("3D1DH1+1", 3),
+            ("4D1KH9", 4),
+            ("4D1DL9", 0),
+            ("2D1!>2", 2),
+            ("2D1!!<1+1", 3),
+            ("3D1r>1", 3),
+            ("3D1ro1KH2", 2),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            assert_eq!(
+                eval(&node, &Environment::new()).unwrap().into_object(),
+                Object::Integer(expected)
+            );
+        }
+
+        for _ in 0..100 {
+            let lexer = Lexer::new("4D6KH3".to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            match eval(&node, &Environment::new()).unwrap().into_object() {
+                Object::Integer(v) => assert!((3..=18).contains(&v)),
+                o => panic!("unexpected object: {}", o),
+            }
+
+            let lexer = Lexer::new("1D6!".to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            match eval(&node, &Environment::new()).unwrap().into_object() {
+                Object::Integer(v) => assert!(v % 6 != 0),
+                o => panic!("unexpected object: {}", o),
+            }
+        }
+    }
+
+    #[test]
+    fn dice_pool_eval_test() {
+        let tests = [
+            ("3B1", Object::List(vec![1, 1, 1])),
+            ("0b6", Object::List(vec![])),
+            ("4B1KH2", Object::List(vec![1, 1])),
+            ("10B1>=1", Object::Integer(10)),
+            ("10B1>=2", Object::Integer(0)),
+            ("(2+3)B1=1", Object::Integer(5)),
+            ("3B1r2<>1", Object::Integer(0)),
+            ("-(2B1)", Object::List(vec![-1, -1])),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            assert_eq!(
+                eval(&node, &Environment::new()).unwrap().into_object(),
+                expected
+            );
+        }
+
+        for _ in 0..100 {
+            let lexer = Lexer::new("10B6>=5".to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            match eval(&node, &Environment::new()).unwrap().into_object() {
+                Object::Integer(v) => assert!((0..=10).contains(&v)),
+                o => panic!("unexpected object: {}", o),
+            }
+        }
+    }
+
+    #[test]
+    fn list_eval_test() {
+        let tests = [
+            ("3B1+1", Object::List(vec![2, 2, 2])),
+            ("2*2B1", Object::List(vec![2, 2])),
+            ("(2B1-1)*3", Object::List(vec![0, 0])),
+            ("2B1+3B1", Object::List(vec![1, 1, 1, 1, 1])),
+            ("sum(3B1+1)", Object::Integer(6)),
+            ("max(2B1+1B1*3)", Object::Integer(3)),
+            ("min(2B1+1B1*3)", Object::Integer(1)),
+            ("sort(1B1*3+2B1)", Object::List(vec![1, 1, 3])),
+            ("len(4B6KH3)", Object::Integer(3)),
+            ("len(0B6)", Object::Integer(0)),
+            ("sum5", Object::Integer(5)),
+            ("(2B1+1B1*3)>1", Object::Integer(1)),
+            ("2B1=2B1", Object::Boolean(true)),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            assert_eq!(
+                eval(&node, &Environment::new()).unwrap().into_object(),
+                expected
+            );
+        }
+
+        for input in ["2B1-1B1", "2B1/0", "max(0B6)"] {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            assert!(eval(&node, &Environment::new()).is_err());
+        }
+
+        assert_eq!(Object::List(vec![3, 5, 1]).to_string(), "[3, 5, 1]");
+    }
+
+    #[test]
+    fn function_eval_test() {
+        let tests = [
+            ("max(1, 5, 3)", Object::Integer(5)),
+            ("min(4, 2B1+1)", Object::Integer(2)),
+            ("sum(1, 2, 3B1)", Object::Integer(6)),
+            ("sum()", Object::Integer(0)),
+            ("sort(3, 1B1)", Object::List(vec![1, 3])),
+            ("count(3B1*2+1B1, 2)", Object::Integer(3)),
+            ("abs(2-5)", Object::Integer(3)),
+            ("clamp(1D1+10, 1, 6)", Object::Integer(6)),
+            ("clamp(0-3, 1, 6)", Object::Integer(1)),
+            ("floor(7, 2)", Object::Integer(3)),
+            ("floor(0-7, 2)", Object::Integer(-4)),
+            ("ceil(7, 2)", Object::Integer(4)),
+            ("ceil(0-7, 2)", Object::Integer(-3)),
+            ("round(7, 2)", Object::Integer(4)),
+            ("round(0-7, 2)", Object::Integer(-4)),
+            ("round(5, 3)", Object::Integer(2)),
+            ("round(4, 3)", Object::Integer(1)),
+            ("floor(6, 0-2)", Object::Integer(-3)),
+            ("rand(3, 3)", Object::Integer(3)),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            assert_eq!(
+                eval(&node, &Environment::new()).unwrap().into_object(),
+                expected,
+                "{}",
+                input
+            );
+        }
+
+        let tests = [
+            ("rand(1, 6)", vec![4], "(rand(1, 6)) ＞ 4"),
+            ("rand(10, 20)+1", vec![1], "(rand(10, 20)+1) ＞ 10+1 ＞ 11"),
+            (
+                "max(2D6, 7)",
+                vec![5, 6],
+                "(max(2D6, 7)) ＞ max(11[5,6], 7) ＞ 11",
+            ),
+        ];
+
+        for (input, values, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+            let env = Environment::with_random(ScriptedRandom::new(values));
+
+            assert_eq!(eval(&node, &env).unwrap().describe(&node), expected);
+        }
+
+        let tests = [
+            (
+                "abs(1, 2)",
+                ErrorKind::ArityMismatch {
+                    form: FunctionForm::new("abs".to_string(), FunctionKind::Prefix),
+                    expected: 1,
+                    found: 2,
+                },
+            ),
+            (
+                "rand(6, 1)",
+                ErrorKind::InvalidArgument("rand(6, 1) has an empty range".to_string()),
+            ),
+            (
+                "clamp(1, 6, 1)",
+                ErrorKind::InvalidArgument("clamp to 6..1 has an empty range".to_string()),
+            ),
+            ("round(1, 0)", ErrorKind::DivisionByZero),
+            (
+                "abs(1=1)",
+                ErrorKind::TypeMismatch {
+                    expected: "integer".to_string(),
+                    found: "boolean".to_string(),
+                },
+            ),
+            (
+                "foo(1, 2)",
+                ErrorKind::UnknownCommand(FunctionForm::new(
+                    "foo".to_string(),
+                    FunctionKind::Prefix,
+                )),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+            let error = eval(&node, &Environment::new()).unwrap_err();
+
+            assert_eq!(error.kind(), &expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn trace_eval_test() {
+        let tests = [
+            ("2D1+1", "(2D1+1) ＞ 2[1,1]+1 ＞ 3"),
+            ("1+2", "(1+2) ＞ 3"),
+            ("(1+2)*3", "((1+2)*3) ＞ 9"),
+            ("3D1KH2", "(3D1KH2) ＞ 2[~~1~~,1,1] ＞ 2"),
+            ("3B1>=1", "(3B1>=1) ＞ [1,1,1]>=1 ＞ 3"),
+            ("1-(2D1-1)", "(1-(2D1-1)) ＞ 1-(2[1,1]-1) ＞ 0"),
+            ("sum(2B1)*2", "(sum(2B1)*2) ＞ sum([1,1])*2 ＞ 4"),
+            ("2*Succ(1D1)", "(2*Succ(1D1)) ＞ 2*(1+1) ＞ 4"),
+            ("1D1+-2", "(1D1+-2) ＞ 1[1]+-2 ＞ -1"),
+            ("-2D1*3", "(-2D1*3) ＞ -2[1,1]*3 ＞ -6"),
+            ("-(1D1+1)", "(-(1D1+1)) ＞ -(1[1]+1) ＞ -2"),
+            ("(1D1=1)=(2=2)", "((1D1=1)=(2=2)) ＞ (1[1]=1)=(2=2) ＞ true"),
+        ];
+        let mut env = Environment::new();
+
+        env.insert(
+            FunctionForm::new("Succ".to_string(), FunctionKind::Prefix),
+            Function::new(
+                Box::new(Node::BinaryExpr {
+                    kind: BinaryExprKind::Add,
+                    lhs: Box::new(Node::NullaryCall {
+                        ident: "x".to_string(),
+                        span: Span::default(),
+                    }),
+                    rhs: Box::new(Node::Integer(1)),
+                    span: Span::default(),
+                }),
+                vec![Parameter::new("x".to_string())],
+            ),
+        );
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            assert_eq!(eval(&node, &env).unwrap().describe(&node), expected);
+        }
+    }
+
+    #[test]
+    fn random_eval_test() {
+        let tests = [
+            ("2D6+1", vec![4, 2], "(2D6+1) ＞ 6[4,2]+1 ＞ 7"),
+            (
+                "4D6KH3",
+                vec![3, 1, 6, 4],
+                "(4D6KH3) ＞ 13[3,~~1~~,6,4] ＞ 13",
+            ),
+            (
+                "4D6r1",
+                vec![3, 1, 1, 6, 4, 2],
+                "(4D6r1) ＞ 15[3,~~1~~,4,~~1~~,2,6] ＞ 15",
+            ),
+            ("3D6!", vec![6, 6, 1, 2, 3], "(3D6!) ＞ 18[6,2,6,3,1] ＞ 18"),
+            (
+                "5B6>=5",
+                vec![5, 1, 6, 4, 2],
+                "(5B6>=5) ＞ [5,1,6,4,2]>=5 ＞ 2",
+            ),
+        ];
+
+        for (input, values, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+            let env = Environment::with_random(ScriptedRandom::new(values));
+
+            assert_eq!(eval(&node, &env).unwrap().describe(&node), expected);
+        }
+
+        let node = Parser::new(Lexer::new("10D100".to_string()))
+            .parse()
+            .unwrap();
+        let a = Environment::with_random(SeededRandom::new(1));
+        let b = Environment::with_random(SeededRandom::new(1));
+
+        for _ in 0..10 {
+            assert_eq!(eval(&node, &a).unwrap(), eval(&node, &b).unwrap());
+        }
+    }
+
     #[test]
     fn boolean_eval_test() {
         let tests = [
@@ -316,9 +862,263 @@ mod test {
             let node = parser.parse().unwrap();
 
             assert_eq!(
-                eval(node, &Environment::new()).unwrap(),
+                eval(&node, &Environment::new()).unwrap().into_object(),
                 Object::Boolean(expected)
             );
         }
     }
+
+    #[test]
+    fn json_eval_test() {
+        let tests = [
+            (
+                "2D6+1>=8",
+                vec![4, 2],
+                r#"{"input":"2D6+1>=8","value":false,"type":"boolean","trace":"6[4,2]+1>=8","dice":[{"expr":"2D6","dice":[{"value":4,"faces":6,"dropped":false},{"value":2,"faces":6,"dropped":false}]}],"judgement":"failure"}"#,
+            ),
+            (
+                "2D6KH1+1D4",
+                vec![1, 5, 3],
+                r#"{"input":"2D6KH1+1D4","value":8,"type":"integer","trace":"5[~~1~~,5]+3[3]","dice":[{"expr":"2D6KH1","dice":[{"value":1,"faces":6,"dropped":true},{"value":5,"faces":6,"dropped":false}]},{"expr":"1D4","dice":[{"value":3,"faces":4,"dropped":false}]}],"judgement":null}"#,
+            ),
+            (
+                "2B6",
+                vec![3, 6],
+                r#"{"input":"2B6","value":[3,6],"type":"list","trace":"[3,6]","dice":[{"expr":"2B6","dice":[{"value":3,"faces":6,"dropped":false},{"value":6,"faces":6,"dropped":false}]}],"judgement":null}"#,
+            ),
+        ];
+
+        for (input, values, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+            let env = Environment::with_random(ScriptedRandom::new(values));
+            let result = eval(&node, &env).unwrap().result(&node);
+
+            assert_eq!(result.to_json(), expected);
+        }
+    }
+
+    #[test]
+    fn exec_eval_test() {
+        let mut env = Environment::with_random(ScriptedRandom::new(vec![4, 2, 5, 1, 1]));
+        let tests = [
+            ("let STR = 3D6", Some("(3D6) ＞ 11[4,2,5] ＞ 11")),
+            ("STR*5", Some("(STR*5) ＞ 11*5 ＞ 55")),
+            (r#"def "adv"x => x + 2D1"#, None),
+            ("adv(STR)", Some("(adv(STR)) ＞ 11+2[1,1] ＞ 13")),
+            ("let STR = STR+1", Some("(STR+1) ＞ 11+1 ＞ 12")),
+            ("STR", Some("(STR) ＞ 12")),
+            (r#"def n"D"m => n*m"#, None),
+            ("2D6", Some("(2D6) ＞ 2*6 ＞ 12")),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let statement = parser.parse_statement().unwrap();
+            let result = exec(statement.clone(), &mut env).unwrap();
+
+            assert_eq!(
+                result.map(|r| r.describe(statement.expr())).as_deref(),
+                expected
+            );
+        }
+
+        let tests = [
+            (
+                "2D6KH1",
+                ErrorKind::InvalidArgument("2D6 is not a dice roll".to_string()),
+            ),
+            (
+                "let x = 1=1",
+                ErrorKind::TypeMismatch {
+                    expected: "integer".to_string(),
+                    found: "boolean".to_string(),
+                },
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let statement = parser.parse_statement().unwrap();
+
+            assert_eq!(exec(statement, &mut env).unwrap_err().kind(), &expected);
+        }
+    }
+
+    #[test]
+    fn lazy_parameter_eval_test() {
+        let tests = [
+            (r#"def "twice"x => x+x"#, "(twice(1D6)) ＞ 3+3 ＞ 6", 1),
+            (
+                r#"def "twice"~x => x+x"#,
+                "(twice(1D6)) ＞ 3[3]+5[5] ＞ 8",
+                2,
+            ),
+        ];
+
+        for (def, expected, rolls) in tests {
+            let mut env = Environment::with_random(ScriptedRandom::new(vec![3, 5]));
+            let lexer = Lexer::new(def.to_string());
+            let mut parser = Parser::new(lexer);
+
+            exec(parser.parse_statement().unwrap(), &mut env).unwrap();
+
+            let lexer = Lexer::new("twice(1D6)".to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+
+            let evaluation = eval(&node, &env).unwrap();
+
+            assert_eq!(evaluation.dice().len(), rolls);
+            assert_eq!(evaluation.describe(&node), expected);
+        }
+    }
+
+    #[test]
+    fn scope_eval_test() {
+        let mut env = Environment::new();
+        let defs = [
+            r#"def "inc"~x => x+1"#,
+            r#"def "g"x => inc(x)"#,
+            "def peek => y",
+            r#"def "outer"y => peek"#,
+        ];
+
+        for def in defs {
+            let lexer = Lexer::new(def.to_string());
+            let mut parser = Parser::new(lexer);
+
+            exec(parser.parse_statement().unwrap(), &mut env).unwrap();
+        }
+
+        // lazy な引数は呼び出し元の環境で評価し、呼び出し先の引数の名前とは衝突しない
+        let lexer = Lexer::new("g(2)".to_string());
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(
+            eval(&parser.parse().unwrap(), &env).unwrap().into_object(),
+            Object::Integer(3)
+        );
+
+        // 呼び出し元の引数は呼び出し先から見えない
+        let lexer = Lexer::new("outer(1)".to_string());
+        let mut parser = Parser::new(lexer);
+
+        assert_eq!(
+            eval(&parser.parse().unwrap(), &env).unwrap_err().kind(),
+            &ErrorKind::UnknownCommand(FunctionForm::new("y".to_string(), FunctionKind::Nullary))
+        );
+    }
+
+    #[test]
+    fn error_eval_test() {
+        let tests = [
+            (
+                "1D",
+                ErrorKind::UnknownCommand(FunctionForm::new(
+                    "D".to_string(),
+                    FunctionKind::Postfix,
+                )),
+                (0, 2),
+            ),
+            ("1/(2-2)", ErrorKind::DivisionByZero, (0, 7)),
+            (
+                "-(1=1)",
+                ErrorKind::TypeMismatch {
+                    expected: "integer".to_string(),
+                    found: "boolean".to_string(),
+                },
+                (0, 6),
+            ),
+            (
+                "170141183460469231731687303715884105727+1",
+                ErrorKind::Overflow,
+                (0, 41),
+            ),
+            (
+                "2*170141183460469231731687303715884105727",
+                ErrorKind::Overflow,
+                (0, 41),
+            ),
+            (
+                "0-170141183460469231731687303715884105727-2",
+                ErrorKind::Overflow,
+                (0, 43),
+            ),
+            (
+                "sum(2B1*170141183460469231731687303715884105727)",
+                ErrorKind::Overflow,
+                (0, 48),
+            ),
+            (
+                "2+1D0",
+                ErrorKind::InvalidDice { count: 1, faces: 0 },
+                (2, 5),
+            ),
+            (
+                "1+(1=1)",
+                ErrorKind::TypeMismatch {
+                    expected: "integer".to_string(),
+                    found: "boolean".to_string(),
+                },
+                (0, 7),
+            ),
+            (
+                "Succ",
+                ErrorKind::ArityMismatch {
+                    form: FunctionForm::new("Succ".to_string(), FunctionKind::Nullary),
+                    expected: 1,
+                    found: 0,
+                },
+                (0, 4),
+            ),
+        ];
+        let mut env = Environment::new();
+
+        env.insert(
+            FunctionForm::new("Succ".to_string(), FunctionKind::Nullary),
+            Function::new(
+                Box::new(Node::Integer(1)),
+                vec![Parameter::new("x".to_string())],
+            ),
+        );
+
+        for (input, expected, (start, end)) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let node = parser.parse().unwrap();
+            let error = eval(&node, &env).unwrap_err();
+
+            assert_eq!(error.kind(), &expected);
+            assert_eq!(error.span(), Some(Span::new(start, end)));
+        }
+
+        let tests = [
+            (
+                "1D6+*2",
+                "1D6+*2\n    ^\nerror: expected number or '(', found '*'",
+            ),
+            (
+                "1D6\n+(2*",
+                "+(2*\n    ^\nline 2: error: expected number or '(', found end of input",
+            ),
+            (
+                "1D170141183460469231731687303715884105728",
+                "1D170141183460469231731687303715884105728\n  ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^\n\
+                 error: number '170141183460469231731687303715884105728' is out of range \
+                 (at most 170141183460469231731687303715884105727)",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let lexer = Lexer::new(input.to_string());
+            let mut parser = Parser::new(lexer);
+            let error = Error::from(parser.parse().unwrap_err());
+
+            assert_eq!(error.diagnostic(input), expected);
+        }
+    }
 }
@@ -1,11 +1,26 @@
-type IntegerType = i128;
+use dyce_lang::{ast, lexer, parser, token, IntegerType};
 
-mod ast;
 mod builtin;
+mod distribution;
 mod environment;
+mod error;
 mod eval;
-mod lexer;
+mod limits;
+mod loader;
 mod object;
-mod parser;
+mod random;
 pub mod repl;
-mod token;
+pub mod script;
+mod simulation;
+
+pub use builtin::{Aggregate, Arity, Builtin, Die};
+pub use distribution::{analyze, Distribution};
+pub use dyce_lang::error::ParseErrorKind;
+pub use environment::{Environment, FunctionForm, FunctionKind};
+pub use error::{Error, ErrorKind};
+pub use limits::Limits;
+pub use loader::{load, load_source};
+pub use object::{DiceTerm, Evaluation, Judgement, Object, RollResult};
+pub use random::{OsRandom, RandomSource, ScriptedRandom, SeededRandom};
+pub use simulation::{simulate, Simulation};
+pub use token::Span;
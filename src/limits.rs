@@ -0,0 +1,248 @@
+use crate::{
+    error::{Error, ErrorKind},
+    IntegerType,
+};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// 一回の評価で使ってよい資源の上限。共有のボットで暴走した命令がプロセスを巻き込まないようにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_steps: usize,
+    pub max_rolls: usize,
+    pub max_dice: IntegerType,
+    pub max_faces: IntegerType,
+}
+
+impl Default for Limits {
+    // 式の入れ子と命令の呼び出しを合わせた深さは、シミュレーションを走らせるスレッドの
+    // 既定のスタック (2 MiB) にデバッグビルドでも収まる値
+    fn default() -> Self {
+        Self {
+            max_depth: 200,
+            max_steps: 1_000_000,
+            max_rolls: 100_000,
+            max_dice: 10_000,
+            max_faces: 1_000_000,
+        }
+    }
+}
+
+impl Limits {
+    pub(crate) fn check_dice(&self, count: IntegerType, faces: IntegerType) -> Result<(), Error> {
+        if count < 0 || faces < 1 {
+            return Err(Error::new(ErrorKind::InvalidDice { count, faces }));
+        }
+
+        if count > self.max_dice {
+            return Err(Error::new(ErrorKind::TooManyDice {
+                count,
+                max: self.max_dice,
+            }));
+        }
+
+        if faces > self.max_faces {
+            return Err(Error::new(ErrorKind::TooManyFaces {
+                faces,
+                max: self.max_faces,
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+// 評価中の再帰の深さと、最も外側の評価を始めてから数えた手数とダイスの個数
+#[derive(Debug, Default)]
+pub(crate) struct Usage {
+    depth: AtomicUsize,
+    steps: AtomicUsize,
+    rolls: AtomicUsize,
+}
+impl Usage {
+    // 最も外側の評価に入るときに数え直す。戻り値を捨てると深さが戻る
+    pub(crate) fn enter<'a>(&'a self, limits: &Limits) -> Result<Guard<'a>, Error> {
+        let guard = self.call(limits)?;
+
+        if guard.0 == 0 {
+            self.steps.store(0, Ordering::Relaxed);
+            self.rolls.store(0, Ordering::Relaxed);
+        }
+
+        if self.steps.fetch_add(1, Ordering::Relaxed) >= limits.max_steps {
+            return Err(Error::new(ErrorKind::StepLimit(limits.max_steps)));
+        }
+
+        Ok(guard)
+    }
+
+    // 命令の呼び出しは式の入れ子よりスタックを多く使うので、式とは別にもう一段数える
+    pub(crate) fn call<'a>(&'a self, limits: &Limits) -> Result<Guard<'a>, Error> {
+        let guard = Guard::new(&self.depth);
+
+        if guard.0 >= limits.max_depth {
+            return Err(Error::new(ErrorKind::RecursionLimit(limits.max_depth)));
+        }
+
+        Ok(guard)
+    }
+
+    pub(crate) fn roll(&self, limits: &Limits) -> Result<(), Error> {
+        if self.rolls.fetch_add(1, Ordering::Relaxed) >= limits.max_rolls {
+            return Err(Error::new(ErrorKind::RollLimit(limits.max_rolls)));
+        }
+
+        Ok(())
+    }
+}
+
+// 増やす前の値を持ち、捨てると数を戻す
+pub(crate) struct Guard<'a>(usize, &'a AtomicUsize);
+impl<'a> Guard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        Self(counter.fetch_add(1, Ordering::Relaxed), counter)
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        self.1.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        distribution::{analyze, distribution},
+        environment::Environment,
+        eval::{eval, exec},
+        lexer::Lexer,
+        loader::load_source,
+        object::Object,
+        parser::Parser,
+        random::ScriptedRandom,
+    };
+
+    fn eval_input(input: &str, env: &Environment) -> Result<IntegerType, Error> {
+        let lexer = Lexer::new(input.to_string());
+        let mut parser = Parser::new(lexer);
+        let node = parser.parse().unwrap();
+
+        match eval(&node, env)?.into_object() {
+            Object::Integer(v) => Ok(v),
+            object => panic!("{:?}", object),
+        }
+    }
+
+    #[test]
+    fn limits_test() {
+        let limits = Limits {
+            max_steps: 10,
+            max_rolls: 6,
+            ..Limits::default()
+        };
+        let tests = [
+            ("loop", ErrorKind::RecursionLimit(200)),
+            ("down(3)", ErrorKind::RecursionLimit(200)),
+            ("1+1+1+1+1+1", ErrorKind::StepLimit(10)),
+            ("7D6", ErrorKind::RollLimit(6)),
+            ("1D6!", ErrorKind::RollLimit(6)),
+            (
+                "10001D6",
+                ErrorKind::TooManyDice {
+                    count: 10001,
+                    max: 10000,
+                },
+            ),
+            (
+                "1D1000001",
+                ErrorKind::TooManyFaces {
+                    faces: 1000001,
+                    max: 1000000,
+                },
+            ),
+        ];
+        let mut env = Environment::with_random(ScriptedRandom::new(vec![6]));
+
+        load_source("loop => loop\n\"down\"n => down(n-1)\n", &mut env).unwrap();
+
+        for (input, expected) in tests {
+            let mut env = env.clone();
+
+            if !matches!(expected, ErrorKind::RecursionLimit(_)) {
+                env.set_limits(limits);
+            }
+
+            assert_eq!(eval_input(input, &env).unwrap_err().kind(), &expected);
+        }
+
+        assert_eq!(
+            analyze("loop", &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(200)
+        );
+
+        // 長い式も深い再帰も、スタックを使い切る前に深さの上限で止まる
+        let flat = vec!["1"; 10000].join("+");
+        let node = Parser::new(Lexer::new(flat)).parse().unwrap();
+
+        assert_eq!(
+            eval(&node, &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(200)
+        );
+        assert_eq!(
+            distribution(&node, &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(200)
+        );
+
+        let def = format!(r#"def "f"x => {}f(x-1)"#, "-".repeat(98));
+        let statement = Parser::new(Lexer::new(def)).parse_statement().unwrap();
+
+        exec(statement, &mut env).unwrap();
+
+        assert_eq!(
+            eval_input("f(1)", &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(200)
+        );
+        assert_eq!(
+            analyze("f(1)", &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(200)
+        );
+
+        let flat = vec!["1"; 120].join("+");
+
+        assert_eq!(eval_input(&flat, &env).unwrap(), 120);
+        assert_eq!(analyze(&flat, &env).unwrap().mean(), 120.0);
+
+        // 命令の呼び出しは式の入れ子より一段多く数える
+        env.set_limits(Limits {
+            max_depth: 6,
+            ..Limits::default()
+        });
+
+        assert_eq!(eval_input("1+1+1+1+1+1", &env).unwrap(), 6);
+        assert_eq!(
+            eval_input("1+1+1+1+1+1+1", &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(6)
+        );
+        assert_eq!(eval_input("abs(abs(-1))", &env).unwrap(), 1);
+        assert_eq!(
+            eval_input("abs(abs(abs(-1)))", &env).unwrap_err().kind(),
+            &ErrorKind::RecursionLimit(6)
+        );
+
+        // 手数とダイスの個数は最も外側の評価ごとに数え直す
+        env.set_limits(limits);
+
+        for _ in 0..3 {
+            assert_eq!(eval_input("6D6", &env).unwrap(), 36);
+        }
+
+        // 複製した環境は数え直しを共有しないので、評価の途中でも別に数える
+        let _outer = env.enter().unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(eval_input("6D6", &env.clone()).unwrap(), 36);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{
+    ast::{Program, Statement},
+    environment::Environment,
+    error::Error,
+    eval::exec,
+    lexer::Lexer,
+    parser::Parser,
+};
+
+// .dyce ファイルの定義を読み込み、登録した定義の個数を返す
+pub fn load_source(source: &str, env: &mut Environment) -> Result<usize, Error> {
+    let lexer = Lexer::new(source.to_string());
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program()?;
+
+    load(&program, env)?;
+
+    Ok(program.defs().len())
+}
+
+// 途中で失敗した場合、それまでの定義は登録されたまま残る
+pub fn load(program: &Program, env: &mut Environment) -> Result<(), Error> {
+    for def in program.defs() {
+        exec(Statement::Def(def.clone()), env)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        ast::Node, error::ErrorKind, eval::eval, object::Object, random::ScriptedRandom,
+        token::Span,
+    };
+    use dyce_lang::error::ParseErrorKind;
+
+    #[test]
+    fn load_test() {
+        let source = "CCB => 1D100\nn\"R\"m => (n)D(m)+1\n\"H\"x => x/2\nx\"U\" => x*2\n";
+        let mut env = Environment::with_random(ScriptedRandom::new(vec![42, 3, 4]));
+
+        assert_eq!(load_source(source, &mut env).unwrap(), 4);
+
+        let tests = [
+            (
+                Node::NullaryCall {
+                    ident: "CCB".to_string(),
+                    span: Span::default(),
+                },
+                42,
+            ),
+            (
+                Node::InfixCall {
+                    ident: "R".to_string(),
+                    lhs: Box::new(Node::Integer(2)),
+                    rhs: Box::new(Node::Integer(6)),
+                    span: Span::default(),
+                },
+                8,
+            ),
+            (
+                Node::PrefixCall {
+                    ident: "H".to_string(),
+                    rhs: Box::new(Node::Integer(9)),
+                    span: Span::default(),
+                },
+                4,
+            ),
+            (
+                Node::PostfixCall {
+                    ident: "U".to_string(),
+                    lhs: Box::new(Node::Integer(9)),
+                    span: Span::default(),
+                },
+                18,
+            ),
+        ];
+
+        for (node, expected) in tests {
+            assert_eq!(
+                eval(&node, &env).unwrap().into_object(),
+                Object::Integer(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn error_load_test() {
+        let tests = [(
+            "CCB => 1D100\nX = 1",
+            ErrorKind::Parse(ParseErrorKind::UnexpectedToken {
+                expected: "'=>'".to_string(),
+                found: "=".to_string(),
+            }),
+            Some(Span::new(15, 16)),
+        )];
+
+        for (source, expected, span) in tests {
+            let error = load_source(source, &mut Environment::new()).unwrap_err();
+
+            assert_eq!(error.kind(), &expected);
+            assert_eq!(error.span(), span);
+        }
+    }
+}
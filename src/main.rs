@@ -1,5 +1,79 @@
-use dyce::repl;
+use clap::{Parser, Subcommand};
+use dyce::{repl, script, script::Format, Environment, SeededRandom};
+use std::{
+    fs,
+    io::{read_to_string, stdin},
+    process::ExitCode,
+};
 
-fn main() {
-    repl::start();
+/// Roll dice expressions. Starts the REPL when no command is given.
+#[derive(Parser)]
+#[command(name = "dyce", version)]
+struct Cli {
+    /// Seed the dice so that every run rolls the same results
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Roll every expression this many times
+    #[arg(long, global = true, default_value_t = 1)]
+    repeat: usize,
+
+    /// Print each result as text or as one JSON object per line
+    #[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Evaluate the given expressions, e.g. dyce roll "2D6+3". Options go before the expressions
+    Roll {
+        #[arg(required = true, allow_hyphen_values = true)]
+        exprs: Vec<String>,
+    },
+    /// Run a script with one statement per line
+    Run { file: String },
+    /// Read statements from standard input, one per line
+    #[command(name = "-")]
+    Stdin,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut env = match cli.seed {
+        Some(seed) => Environment::with_random(SeededRandom::new(seed)),
+        None => Environment::new(),
+    };
+
+    let result = match cli.command {
+        None => {
+            repl::start(env, cli.format);
+            Ok(())
+        }
+        Some(Command::Roll { exprs }) => exprs.iter().try_for_each(|expr| {
+            script::run_line(expr, &mut env, cli.repeat, cli.format)
+                .inspect_err(|e| eprintln!("{}", e.diagnostic(expr)))
+        }),
+        Some(Command::Run { file }) => match fs::read_to_string(&file) {
+            Ok(source) => script::run(&file, &source, &mut env, cli.repeat, cli.format),
+            Err(e) => {
+                eprintln!("error: cannot read {}: {}", file, e);
+                return ExitCode::FAILURE;
+            }
+        },
+        Some(Command::Stdin) => match read_to_string(stdin()) {
+            Ok(source) => script::run("<stdin>", &source, &mut env, cli.repeat, cli.format),
+            Err(e) => {
+                eprintln!("error: cannot read standard input: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
 }
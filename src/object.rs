@@ -1,9 +1,26 @@
-use crate::IntegerType;
+use crate::{
+    ast::{needs_parens, Node, PRIMARY_PRECEDENCE},
+    builtin::Die,
+    IntegerType,
+};
+use serde::Serialize;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
 pub enum Object {
     Integer(IntegerType),
     Boolean(bool),
+    List(Vec<IntegerType>),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) => "integer",
+            Object::Boolean(_) => "boolean",
+            Object::List(_) => "list",
+        }
+    }
 }
 
 impl std::fmt::Display for Object {
@@ -14,7 +31,137 @@ impl std::fmt::Display for Object {
             match self {
                 Object::Integer(v) => v.to_string(),
                 Object::Boolean(v) => v.to_string(),
+                Object::List(v) => format!(
+                    "[{}]",
+                    v.iter()
+                        .map(|v| v.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
             }
         )
     }
 }
+
+// 一回のダイスロール "4D6kh3" とその出目
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiceTerm {
+    expr: String,
+    dice: Vec<Die>,
+}
+impl DiceTerm {
+    pub fn new(expr: String, dice: Vec<Die>) -> Self {
+        Self { expr, dice }
+    }
+
+    pub fn expr(&self) -> &str {
+        &self.expr
+    }
+
+    pub fn dice(&self) -> &[Die] {
+        &self.dice
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Judgement {
+    Success,
+    Failure,
+}
+
+// JSON で出力するための評価結果
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RollResult {
+    input: String,
+    value: Object,
+    #[serde(rename = "type")]
+    type_name: &'static str,
+    trace: String,
+    dice: Vec<DiceTerm>,
+    judgement: Option<Judgement>,
+}
+impl RollResult {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("RollResult is always serializable")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+    object: Object,
+    trace: String,
+    precedence: u8,
+    dice: Vec<DiceTerm>,
+}
+impl Evaluation {
+    pub fn new(object: Object, trace: String) -> Self {
+        Self {
+            object,
+            trace,
+            precedence: PRIMARY_PRECEDENCE,
+            dice: Vec::new(),
+        }
+    }
+
+    pub fn with_precedence(self, precedence: u8) -> Self {
+        Self { precedence, ..self }
+    }
+
+    pub fn with_dice(self, dice: Vec<DiceTerm>) -> Self {
+        Self { dice, ..self }
+    }
+
+    pub fn into_object(self) -> Object {
+        self.object
+    }
+
+    // この式の中で振ったダイスを振った順に並べたもの
+    pub fn dice(&self) -> &[DiceTerm] {
+        &self.dice
+    }
+
+    // 各ダイスの出目を展開した式
+    pub fn trace(&self) -> &str {
+        &self.trace
+    }
+
+    // BCDice 形式の結果表示 "(2D6+1) ＞ 6[4,2]+1 ＞ 7"
+    pub fn describe(&self, input: &Node) -> String {
+        let input = input.to_string();
+        let result = self.object.to_string();
+
+        if self.trace == input || self.trace == result {
+            format!("({}) ＞ {}", input, result)
+        } else {
+            format!("({}) ＞ {} ＞ {}", input, self.trace, result)
+        }
+    }
+
+    // 比較の結果は成功か失敗かの判定になる
+    pub fn result(&self, input: &Node) -> RollResult {
+        let judgement = match self.object {
+            Object::Boolean(true) => Some(Judgement::Success),
+            Object::Boolean(false) => Some(Judgement::Failure),
+            _ => None,
+        };
+
+        RollResult {
+            input: input.to_string(),
+            value: self.object.clone(),
+            type_name: self.object.type_name(),
+            trace: self.trace.clone(),
+            dice: self.dice.clone(),
+            judgement,
+        }
+    }
+
+    // 親の式に埋め込むときの trace。必要なら括弧で囲む
+    pub fn operand_trace(&self, parent: u8, is_rhs: bool) -> String {
+        if needs_parens(parent, self.precedence, is_rhs) {
+            format!("({})", self.trace)
+        } else {
+            self.trace.clone()
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use crate::IntegerType;
+use rand::{
+    rngs::{OsRng, StdRng},
+    Rng, SeedableRng,
+};
+
+pub trait RandomSource: Send {
+    // 1 以上 faces 以下の出目を返す
+    fn roll(&mut self, faces: IntegerType) -> IntegerType;
+}
+
+pub struct SeededRandom {
+    rng: StdRng,
+}
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn from_entropy() -> Self {
+        Self {
+            rng: StdRng::from_entropy(),
+        }
+    }
+}
+impl RandomSource for SeededRandom {
+    fn roll(&mut self, faces: IntegerType) -> IntegerType {
+        self.rng.gen_range(1..=faces)
+    }
+}
+
+pub struct OsRandom;
+impl RandomSource for OsRandom {
+    fn roll(&mut self, faces: IntegerType) -> IntegerType {
+        OsRng.gen_range(1..=faces)
+    }
+}
+
+// 与えられた出目を順番に返す。最後まで使い切ったら先頭に戻る。空の場合は常に最大値を返す
+pub struct ScriptedRandom {
+    values: Vec<IntegerType>,
+    position: usize,
+}
+impl ScriptedRandom {
+    pub fn new(values: Vec<IntegerType>) -> Self {
+        Self {
+            values,
+            position: 0,
+        }
+    }
+}
+impl RandomSource for ScriptedRandom {
+    fn roll(&mut self, faces: IntegerType) -> IntegerType {
+        if self.values.is_empty() {
+            return faces;
+        }
+
+        let value = self.values[self.position % self.values.len()];
+
+        self.position += 1;
+
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_random_test() {
+        let mut a = SeededRandom::new(42);
+        let mut b = SeededRandom::new(42);
+
+        for _ in 0..100 {
+            let v = a.roll(6);
+
+            assert_eq!(v, b.roll(6));
+            assert!((1..=6).contains(&v));
+        }
+    }
+
+    #[test]
+    fn scripted_random_test() {
+        let mut random = ScriptedRandom::new(vec![3, 1, 4]);
+
+        assert_eq!(
+            (0..5).map(|_| random.roll(6)).collect::<Vec<_>>(),
+            vec![3, 1, 4, 3, 1]
+        );
+    }
+}
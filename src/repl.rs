@@ -1,28 +1,419 @@
-use crate::{environment::Environment, eval::eval, lexer::Lexer, parser::Parser};
-use std::io::{stdin, stdout, Write};
+use crate::{
+    distribution::analyze,
+    environment::{Environment, Function, FunctionForm},
+    error::{Error, ErrorKind},
+    eval::exec,
+    lexer::Lexer,
+    loader::load_source,
+    parser::Parser,
+    random::SeededRandom,
+    script::Format,
+    simulation::simulate,
+};
+use rustyline::{
+    completion::{Completer, FilenameCompleter, Pair},
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::DefaultHistory,
+    validate::Validator,
+    Context, Editor, Helper,
+};
+use std::{fs, path::PathBuf, thread};
 
-const PROMPT: &'static str = ">> ";
+const PROMPT: &str = ">> ";
+const SIMULATION_RUNS: usize = 100_000;
+const KEYWORDS: [&str; 2] = ["let", "def"];
+const META_COMMANDS: [&str; 5] = [":load", ":defs", ":seed", ":help", ":quit"];
+
+const HELP: &str = "\
+EXPR                evaluate and roll an expression, e.g. 2D6+1
+?EXPR               show the probability distribution of an expression
+let NAME = EXPR     roll once and bind the result to NAME
+def PAT => EXPR     define a command, e.g. def n\"R\"m => (n)D(m)
+                    a definition hides a builtin of the same form
+                    arguments are rolled once; write ~x to reroll x on each use
+:load FILE          load definitions (one PAT => EXPR per line) from FILE
+:defs               list the commands defined in this session
+:seed [N]           reseed the dice, or use a random seed without N
+:help [COMMAND]     show this help, or help for a command
+:quit               exit the REPL";
+
+// let と def による定義はセッションの間ずっと残る
+pub fn start(mut env: Environment, format: Format) {
+    let mut editor = match Editor::<Completion, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("error: cannot start the line editor: {}", e);
+            return;
+        }
+    };
+    let history = history_path();
+
+    // 履歴がまだ無いのは初回の起動なので無視する
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
 
-pub fn start() {
     loop {
-        print!("{}", PROMPT);
-        stdout().flush().unwrap();
+        editor.set_helper(Some(Completion::new(&env)));
+
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                break;
+            }
+        };
+
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+
+        if let Some(command) = line.trim().strip_prefix(':') {
+            match meta(command, &mut env) {
+                Some(output) if output.is_empty() => {}
+                Some(output) => println!("{}", output),
+                None => break,
+            }
+
+            continue;
+        }
+
+        // "?" から始まる行は振らずに確率分布を表示する。厳密に求められない式は試行で近似する
+        if let Some(input) = line.trim_start().strip_prefix('?') {
+            match analyze(input, &env) {
+                Ok(distribution) => {
+                    println!("{}", distribution);
+                    println!("mean: {:.4}", distribution.mean());
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::Unsupported(_)) => match simulate(
+                    input,
+                    &env,
+                    SIMULATION_RUNS,
+                    env.seed(),
+                    thread::available_parallelism().map_or(1, |n| n.get()),
+                ) {
+                    Ok(simulation) => println!("{}", simulation),
+                    Err(e) => println!("{}", e.diagnostic(input)),
+                },
+                Err(e) => println!("{}", e.diagnostic(input)),
+            }
 
-        let mut line = String::new();
+            continue;
+        }
 
-        stdin().read_line(&mut line).expect("Failed to read line");
+        if line.trim().is_empty() {
+            continue;
+        }
 
-        let lexer = Lexer::new(line);
+        let lexer = Lexer::new(line.clone());
         let mut parser = Parser::new(lexer);
-        let node = match parser.parse() {
-            Ok(n) => n,
-            _ => continue,
-        };
-        let result = match eval(node, &Environment::new()) {
-            Ok(n) => n,
-            _ => continue,
+        let statement = match parser.parse_statement().map_err(Error::from) {
+            Ok(s) => s,
+            Err(e) => {
+                println!("{}", e.diagnostic(&line));
+                continue;
+            }
         };
 
-        println!("{}", result);
+        match exec(statement.clone(), &mut env) {
+            Ok(Some(result)) => println!("{}", format.render(&result, statement.expr())),
+            Ok(None) => {}
+            Err(e) => println!("{}", e.diagnostic(&line)),
+        }
+    }
+
+    if let Some(path) = &history {
+        let saved = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .map_err(ReadlineError::from)
+            .and_then(|_| editor.save_history(path));
+
+        if let Err(e) = saved {
+            eprintln!("error: cannot save history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("dyce").join("history"))
+}
+
+// ":" から始まる行。表示する内容を返し、REPL を終えるときは None を返す
+fn meta(command: &str, env: &mut Environment) -> Option<String> {
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+
+    let output = match name {
+        "q" | "quit" => return None,
+        "load" if !argument.is_empty() => load(argument, env),
+        "defs" => defs(env),
+        "seed" if argument.is_empty() => {
+            env.set_random(SeededRandom::from_entropy());
+            String::new()
+        }
+        "seed" => match argument.parse::<u64>() {
+            Ok(seed) => {
+                env.set_random(SeededRandom::new(seed));
+                String::new()
+            }
+            Err(_) => format!("error: invalid seed '{}'", argument),
+        },
+        "h" | "help" if argument.is_empty() => HELP.to_string(),
+        "h" | "help" => help(argument, env),
+        "load" => "usage: :load FILE".to_string(),
+        _ => format!("error: unknown meta-command ':{}' (try :help)", name),
+    };
+
+    Some(output)
+}
+
+// 途中で失敗したときに一部の定義だけが残らないよう、複製した環境に読み込んでから差し替える
+fn load(path: &str, env: &mut Environment) -> String {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => return format!("error: cannot read {}: {}", path, e),
+    };
+    let mut loaded = env.clone();
+
+    match load_source(&source, &mut loaded) {
+        Ok(count) => {
+            *env = loaded;
+            format!("loaded {} definition(s) from {}", count, path)
+        }
+        Err(e) => format!("{}:\n{}", path, e.diagnostic(&source)),
+    }
+}
+
+fn defs(env: &Environment) -> String {
+    let mut functions = env.functions().collect::<Vec<_>>();
+
+    functions.sort_by(|(a, _), (b, _)| a.name().cmp(b.name()));
+
+    if functions.is_empty() {
+        return "no commands defined".to_string();
+    }
+
+    functions
+        .into_iter()
+        .map(|(form, function)| definition(form, function))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn definition(form: &FunctionForm, function: &Function) -> String {
+    format!(
+        "{:<8}{} => {}",
+        form.kind().to_string(),
+        form.pattern(function.parameters()),
+        function
+    )
+}
+
+fn help(name: &str, env: &Environment) -> String {
+    let lines = env
+        .builtins()
+        .filter(|b| b.name() == name)
+        .map(|b| format!("builtin: {}", b.help()))
+        .chain(
+            env.functions()
+                .filter(|(form, _)| form.name() == name)
+                .map(|(form, function)| definition(form, function)),
+        )
+        .collect::<Vec<_>>();
+
+    if lines.is_empty() {
+        return format!("error: no command named '{}'", name);
+    }
+
+    lines.join("\n")
+}
+
+// 行の編集中に Tab で命令名を補完する。命令名は入力のたびに環境から取り直す
+struct Completion {
+    names: Vec<String>,
+    files: FilenameCompleter,
+}
+impl Completion {
+    fn new(env: &Environment) -> Self {
+        let mut names = env
+            .builtins()
+            .map(|b| b.name().to_string())
+            .chain(KEYWORDS.iter().map(|k| k.to_string()))
+            .chain(env.functions().map(|(form, _)| form.name().to_string()))
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names.dedup();
+
+        Self {
+            names,
+            files: FilenameCompleter::new(),
+        }
+    }
+}
+
+// 補完を始める位置と候補
+fn candidates(line: &str, pos: usize, names: &[String]) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+
+    if before.starts_with(':') && !before.contains(char::is_whitespace) {
+        let commands = META_COMMANDS
+            .iter()
+            .filter(|c| c.starts_with(before))
+            .map(|c| c.to_string())
+            .collect();
+
+        return (0, commands);
+    }
+
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphabetic())
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let word = &before[start..];
+
+    if word.is_empty() {
+        return (pos, Vec::new());
+    }
+
+    let names = names
+        .iter()
+        .filter(|name| name.starts_with(word))
+        .cloned()
+        .collect();
+
+    (start, names)
+}
+
+impl Completer for Completion {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        if line.starts_with(":load ") {
+            return self.files.complete(line, pos, ctx);
+        }
+
+        let (start, names) = candidates(line, pos, &self.names);
+        let pairs = names
+            .into_iter()
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: name,
+            })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::random::ScriptedRandom;
+
+    #[test]
+    fn meta_test() {
+        let tests = [
+            ("seed abc", Some("error: invalid seed 'abc'")),
+            ("seed 42", Some("")),
+            ("load", Some("usage: :load FILE")),
+            ("load ", Some("usage: :load FILE")),
+            (
+                "frobnicate",
+                Some("error: unknown meta-command ':frobnicate' (try :help)"),
+            ),
+            ("help nosuch", Some("error: no command named 'nosuch'")),
+            ("defs", Some("no commands defined")),
+            ("quit", None),
+            ("q", None),
+        ];
+
+        for (command, expected) in tests {
+            let mut env = Environment::new();
+
+            assert_eq!(meta(command, &mut env).as_deref(), expected, "{}", command);
+        }
+    }
+
+    #[test]
+    fn definition_test() {
+        let mut env = Environment::with_random(ScriptedRandom::new(vec![]));
+        let defs = [
+            "def CCB => 1D100",
+            r#"def "double"x => x+x"#,
+            r#"def n"R"~m => (n)D(m)"#,
+            r#"def x"U" => x*2"#,
+        ];
+
+        for def in defs {
+            let lexer = Lexer::new(def.to_string());
+            let mut parser = Parser::new(lexer);
+
+            exec(parser.parse_statement().unwrap(), &mut env).unwrap();
+        }
+
+        // 名前の順に並ぶ
+        assert_eq!(
+            meta("defs", &mut env).as_deref(),
+            Some(
+                [
+                    "nullary CCB => 1D100",
+                    "infix   n \"R\" ~m => (n)D(m)",
+                    "postfix x \"U\" => x*2",
+                    "prefix  \"double\" x => x+x",
+                ]
+                .join("\n")
+                .as_str()
+            )
+        );
+        assert_eq!(
+            meta("help double", &mut env).as_deref(),
+            Some("prefix  \"double\" x => x+x")
+        );
+    }
+
+    #[test]
+    fn candidates_test() {
+        let names = ["CCB", "CC", "D", "def", "sum", "sort"].map(|n| n.to_string());
+        let tests = [
+            ("1+CC", 4, (2, vec!["CCB", "CC"])),
+            ("so", 2, (0, vec!["sort"])),
+            ("2D", 2, (1, vec!["D"])),
+            ("s(1)", 1, (0, vec!["sum", "sort"])),
+            ("1+", 2, (2, vec![])),
+            (":l", 2, (0, vec![":load"])),
+            (":", 1, (0, META_COMMANDS.to_vec())),
+        ];
+
+        for (line, pos, (start, expected)) in tests {
+            assert_eq!(
+                candidates(line, pos, &names),
+                (start, expected.iter().map(|n| n.to_string()).collect())
+            );
+        }
     }
 }
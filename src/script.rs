@@ -0,0 +1,98 @@
+use crate::{
+    ast::{Node, Statement},
+    environment::Environment,
+    error::Error,
+    eval::exec,
+    lexer::Lexer,
+    object::Evaluation,
+    parser::Parser,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+impl Format {
+    // JSON は一つの結果を一行に収める
+    pub fn render(&self, result: &Evaluation, input: &Node) -> String {
+        match self {
+            Format::Text => result.describe(input),
+            Format::Json => result.result(input).to_json(),
+        }
+    }
+}
+
+// 一行を実行して結果を表示する。式の行だけを repeat 回振り、let と def は一度だけ実行する
+pub fn run_line(
+    line: &str,
+    env: &mut Environment,
+    repeat: usize,
+    format: Format,
+) -> Result<(), Error> {
+    let lexer = Lexer::new(line.to_string());
+    let mut parser = Parser::new(lexer);
+    let statement = parser.parse_statement()?;
+    let times = match statement {
+        Statement::Expr(_) => repeat,
+        _ => 1,
+    };
+
+    for _ in 0..times {
+        if let Some(result) = exec(statement.clone(), env)? {
+            println!("{}", format.render(&result, statement.expr()));
+        }
+    }
+
+    Ok(())
+}
+
+// 空行を飛ばして一行ずつ実行し、最初に失敗した行で止める
+pub fn run(
+    name: &str,
+    source: &str,
+    env: &mut Environment,
+    repeat: usize,
+    format: Format,
+) -> Result<(), Error> {
+    for (i, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Err(e) = run_line(line, env, repeat, format) {
+            eprintln!("{}:{}:\n{}", name, i + 1, e.diagnostic(line));
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        environment::{FunctionForm, FunctionKind},
+        error::ErrorKind,
+    };
+
+    #[test]
+    fn run_test() {
+        let mut env = Environment::new();
+
+        assert!(run("test", "let A = 1D6\n\nA*2\n", &mut env, 3, Format::Text).is_ok());
+        assert!(env
+            .function(
+                &FunctionForm::new("A".to_string(), FunctionKind::Nullary),
+                0
+            )
+            .is_ok());
+
+        let error = run("test", "1+1\n1D0\n1+", &mut env, 1, Format::Json).unwrap_err();
+
+        assert_eq!(error.kind(), &ErrorKind::InvalidDice { count: 1, faces: 0 });
+        assert!(run_line("1+", &mut env, 1, Format::Text).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::{
+    ast::Node,
+    environment::Environment,
+    error::{Error, ErrorKind},
+    eval::eval,
+    lexer::Lexer,
+    object::Object,
+    parser::Parser,
+    random::SeededRandom,
+    IntegerType,
+};
+use std::{collections::BTreeMap, thread};
+
+const BAR_WIDTH: f64 = 40.0;
+const PERCENTILES: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+// seed を与える単位の試行回数。スレッドの数によらず同じ試行に同じ乱数源を使う
+const CHUNK_RUNS: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    samples: Vec<IntegerType>,
+}
+impl Simulation {
+    fn new(mut samples: Vec<IntegerType>) -> Self {
+        samples.sort();
+
+        Self { samples }
+    }
+
+    pub fn runs(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.samples.iter().map(|&v| v as f64).sum::<f64>() / self.runs() as f64
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        let mean = self.mean();
+        let variance = self
+            .samples
+            .iter()
+            .map(|&v| (v as f64 - mean).powi(2))
+            .sum::<f64>()
+            / self.runs() as f64;
+
+        variance.sqrt()
+    }
+
+    pub fn min(&self) -> Option<IntegerType> {
+        self.samples.first().copied()
+    }
+
+    pub fn max(&self) -> Option<IntegerType> {
+        self.samples.last().copied()
+    }
+
+    // 最近接順位法による百分位数。試行が無ければ None
+    pub fn percentile(&self, p: f64) -> Option<IntegerType> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let rank = (p / 100.0 * self.runs() as f64).ceil() as usize;
+
+        self.samples.get(rank.clamp(1, self.runs()) - 1).copied()
+    }
+
+    pub fn histogram(&self) -> BTreeMap<IntegerType, usize> {
+        let mut histogram = BTreeMap::new();
+
+        for &v in &self.samples {
+            *histogram.entry(v).or_insert(0) += 1;
+        }
+
+        histogram
+    }
+}
+
+impl std::fmt::Display for Simulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (min, max) = match (self.min(), self.max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return write!(f, "runs: 0"),
+        };
+
+        writeln!(
+            f,
+            "runs: {}, mean: {:.4}, sd: {:.4}, min: {}, max: {}",
+            self.runs(),
+            self.mean(),
+            self.std_dev(),
+            min,
+            max
+        )?;
+        writeln!(
+            f,
+            "{}",
+            PERCENTILES
+                .iter()
+                .map(|&p| format!("p{}: {}", p, self.percentile(p).unwrap_or(0)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+
+        let histogram = self.histogram();
+        let width = histogram
+            .keys()
+            .map(|v| v.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let highest = histogram.values().copied().max().unwrap_or(0) as f64;
+
+        for (i, (value, count)) in histogram.into_iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+
+            write!(
+                f,
+                "{:>width$} {:>8.4}% {}",
+                value,
+                count as f64 / self.runs() as f64 * 100.0,
+                "#".repeat((count as f64 / highest * BAR_WIDTH).round() as usize),
+                width = width
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// 同じ seed なら threads によらず同じ結果になる
+pub fn simulate(
+    input: &str,
+    env: &Environment,
+    runs: usize,
+    seed: u64,
+    threads: usize,
+) -> Result<Simulation, Error> {
+    let lexer = Lexer::new(input.to_string());
+    let mut parser = Parser::new(lexer);
+    let node = parser.parse()?;
+
+    simulation(&node, env, runs, seed, threads)
+}
+
+pub fn simulation(
+    node: &Node,
+    env: &Environment,
+    runs: usize,
+    seed: u64,
+    threads: usize,
+) -> Result<Simulation, Error> {
+    let chunks = runs.div_ceil(CHUNK_RUNS);
+    let threads = threads.clamp(1, chunks.max(1));
+
+    // 試行を決まった大きさの塊に分け、塊ごとに別の seed の乱数源を持たせる。
+    // 標本は並べ替えるので、どのスレッドがどの塊を受け持っても結果は変わらない
+    let samples = thread::scope(|s| {
+        let handles = (0..threads)
+            .map(|i| {
+                s.spawn(move || {
+                    let mut samples = Vec::new();
+
+                    for chunk in (i..chunks).step_by(threads) {
+                        let env = env.fork(SeededRandom::new(seed.wrapping_add(chunk as u64)));
+                        let runs = CHUNK_RUNS.min(runs - chunk * CHUNK_RUNS);
+
+                        samples.extend(sample(node, &env, runs)?);
+                    }
+
+                    Ok(samples)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("simulation thread panicked"))
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    Ok(Simulation::new(samples.into_iter().flatten().collect()))
+}
+
+fn sample(node: &Node, env: &Environment, runs: usize) -> Result<Vec<IntegerType>, Error> {
+    (0..runs)
+        .map(|_| match eval(node, env)?.into_object() {
+            Object::Integer(v) => Ok(v),
+            Object::Boolean(v) => Ok(v as IntegerType),
+            object => Err(Error::new(ErrorKind::TypeMismatch {
+                expected: "integer".to_string(),
+                found: object.type_name().to_string(),
+            })),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::environment::{Function, FunctionForm, FunctionKind};
+    use crate::token::Span;
+
+    #[test]
+    fn simulation_test() {
+        let env = Environment::new();
+        let a = simulate("3D6", &env, 1000, 42, 4).unwrap();
+        let b = simulate("3D6", &env, 1000, 42, 4).unwrap();
+
+        assert_eq!(a, b);
+        assert_eq!(a.runs(), 1000);
+        assert_eq!(a.histogram().values().sum::<usize>(), 1000);
+        assert!(a.min().unwrap() >= 3 && a.max().unwrap() <= 18);
+        assert!((a.mean() - 10.5).abs() < 0.5);
+        assert!(a.percentile(5.0) <= a.percentile(50.0));
+        assert!(a.percentile(50.0) <= a.percentile(95.0));
+
+        // 試行の塊ごとに seed を与えるので、スレッドの数が違っても結果は同じ
+        let a = simulate("3D6", &env, 2500, 42, 1).unwrap();
+
+        for threads in [2, 3, 8] {
+            assert_eq!(simulate("3D6", &env, 2500, 42, threads).unwrap(), a);
+        }
+
+        // 環境の乱数源を固定すれば seed も再現できる
+        let a = Environment::with_random(SeededRandom::new(1));
+        let b = Environment::with_random(SeededRandom::new(1));
+
+        assert_eq!(a.seed(), b.seed());
+
+        let simulation = simulate("1D6>=4", &env, 100, 0, 1).unwrap();
+
+        assert!(simulation.max().unwrap() <= 1);
+    }
+
+    #[test]
+    fn user_command_simulation_test() {
+        let mut env = Environment::new();
+
+        env.insert(
+            FunctionForm::new("CCB".to_string(), FunctionKind::Nullary),
+            Function::new(
+                Box::new(Node::InfixCall {
+                    ident: "D".to_string(),
+                    lhs: Box::new(Node::Integer(1)),
+                    rhs: Box::new(Node::Integer(100)),
+                    span: Span::default(),
+                }),
+                Vec::new(),
+            ),
+        );
+
+        let simulation = simulate("CCB", &env, 500, 7, 2).unwrap();
+
+        assert!(simulation.min().unwrap() >= 1 && simulation.max().unwrap() <= 100);
+        assert!(simulate("2B6", &env, 10, 0, 1).is_err());
+    }
+
+    #[test]
+    fn percentile_test() {
+        let simulation = Simulation::new((1..=100).rev().collect());
+
+        assert_eq!(simulation.percentile(0.0), Some(1));
+        assert_eq!(simulation.percentile(50.0), Some(50));
+        assert_eq!(simulation.percentile(95.0), Some(95));
+        assert_eq!(simulation.percentile(100.0), Some(100));
+        assert!((simulation.mean() - 50.5).abs() < 1e-9);
+
+        let simulation = simulate("3D6", &Environment::new(), 0, 42, 4).unwrap();
+
+        assert_eq!(simulation.runs(), 0);
+        assert_eq!(simulation.percentile(50.0), None);
+        assert_eq!(simulation.to_string(), "runs: 0");
+    }
+}